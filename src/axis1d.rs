@@ -0,0 +1,109 @@
+//! A single axis of the simulation. Signum gravity on one axis never looks
+//! at the others, so each axis can be stepped, and its period found, alone.
+
+use crate::{Axis, OverflowError, System};
+use alloc::vec::Vec;
+use itertools::Itertools;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Axis1D {
+    bodies: Vec<(isize, isize)>,
+}
+
+impl Axis1D {
+    /// Takes a `(position, velocity)` pair per body.
+    pub fn new(bodies: Vec<(isize, isize)>) -> Self {
+        Self { bodies }
+    }
+
+    /// Applies one step of signum gravity along this axis.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a position or velocity overflows, as `System::step` does
+    /// under `OverflowPolicy::Checked`.
+    pub fn step(&mut self) {
+        let add = |body: usize, a: isize, b: isize| {
+            a.checked_add(b)
+                .unwrap_or_else(|| panic!("{}", OverflowError { body }))
+        };
+        for (i, j) in (0..self.bodies.len()).tuple_combinations() {
+            let pull = self.bodies[j].0.cmp(&self.bodies[i].0) as isize;
+            self.bodies[i].1 = add(i, self.bodies[i].1, pull);
+            self.bodies[j].1 = add(j, self.bodies[j].1, -pull);
+        }
+        for (body, (position, velocity)) in self.bodies.iter_mut().enumerate() {
+            *position = add(body, *position, *velocity);
+        }
+    }
+
+    /// The `(position, velocity)` of every body.
+    pub fn state(&self) -> &[(isize, isize)] {
+        &self.bodies
+    }
+
+    /// Number of steps until this axis returns to its current state, or
+    /// `None` if it hasn't within `max_steps`. Bodies with nonzero total
+    /// velocity drift off and never return, so that's `None` without
+    /// stepping.
+    pub fn find_period(&self, max_steps: usize) -> Option<usize> {
+        if self.bodies.iter().map(|&(_, v)| v).sum::<isize>() != 0 {
+            return None;
+        }
+        let mut axis = self.clone();
+        for steps in 1..=max_steps {
+            axis.step();
+            if axis == *self {
+                return Some(steps);
+            }
+        }
+        None
+    }
+}
+
+impl System {
+    /// Extracts the positions and velocities along `axis`.
+    pub fn axis(&self, axis: Axis) -> Axis1D {
+        Axis1D::new(
+            self.bodies()
+                .map(|b| (b.position().component(axis), b.velocity().component(axis)))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn matches_system() {
+        let mut system = System::aoc_example_1();
+        let mut x = system.axis(Axis::X);
+        for _ in 0..10 {
+            system.step();
+            x.step();
+        }
+        assert_eq!(x, system.axis(Axis::X));
+        assert_eq!(x.state()[0], (2, -3));
+    }
+
+    #[test]
+    fn period() {
+        let system = System::aoc_example_1();
+        let periods = system.axis_periods(100).unwrap();
+        for (axis, &period) in Axis::ALL.iter().zip(periods.iter()) {
+            assert_eq!(system.axis(*axis).find_period(100), Some(period));
+            assert_eq!(system.axis(*axis).find_period(period - 1), None);
+        }
+        assert_eq!(Axis1D::new(Vec::new()).find_period(1), Some(1));
+        assert_eq!(Axis1D::new(vec![(0, 0), (5, 1)]).find_period(1000), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "overflow")]
+    fn overflow() {
+        Axis1D::new(vec![(isize::MAX, 1)]).step();
+    }
+}
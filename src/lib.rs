@@ -0,0 +1,2890 @@
+//! Integer n-body gravity simulation.
+//!
+//! The core math only needs `alloc`. With default features disabled the crate
+//! is `#![no_std]`; this should keep building in CI with:
+//!
+//! ```text
+//! cargo build --lib --no-default-features --target thumbv7em-none-eabihf
+//! cargo build --lib --no-default-features --features serde,nalgebra \
+//!     --target thumbv7em-none-eabihf
+//! cargo test --no-default-features
+//! ```
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+mod axis1d;
+mod parse;
+#[cfg(feature = "scenario")]
+mod scenario;
+mod vecn;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use axis1d::Axis1D;
+pub use parse::ParseError;
+#[cfg(feature = "std")]
+pub use parse::{parse_bodies, parse_systems, BodyReader};
+#[cfg(feature = "scenario")]
+pub use scenario::{Scenario, ScenarioBody, ScenarioError};
+pub use vecn::{BodyN, VecN};
+
+use alloc::collections::VecDeque;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::convert::{Infallible, TryFrom};
+use core::error::Error;
+use core::fmt::{self, Write};
+use core::hash::Hash;
+use core::iter::FromIterator;
+use core::ops::{AddAssign, Neg, Sub};
+use itertools::Itertools;
+use log::{debug, info};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use std::collections::hash_map::{DefaultHasher, RandomState};
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::hash::{BuildHasher, Hasher};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// A 3-vector. `T` defaults to `isize`, the only component type `Body` and
+/// `System` step; other types (e.g. `num_rational::Rational64`) get the
+/// vector arithmetic but no simulation.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Vec3<T = isize> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+
+impl<T: Default> Vec3<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T: Neg<Output = T>> Neg for Vec3<T> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+}
+
+impl<T: Sub<Output = T>> Sub for Vec3<T> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            x: self.x - other.x,
+            y: self.y - other.y,
+            z: self.z - other.z,
+        }
+    }
+}
+
+pub type Velocity = Vec3;
+pub type Position = Vec3;
+
+/// How the integer model handles coordinates or velocities leaving `isize`'s
+/// range.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Stop the step with an `OverflowError`.
+    #[default]
+    Checked,
+    Saturating,
+    Wrapping,
+}
+
+impl OverflowPolicy {
+    fn add(self, a: isize, b: isize) -> Option<isize> {
+        match self {
+            Self::Checked => a.checked_add(b),
+            Self::Saturating => Some(a.saturating_add(b)),
+            Self::Wrapping => Some(a.wrapping_add(b)),
+        }
+    }
+
+    fn add_vec(self, a: Vec3, b: Vec3) -> Option<Vec3> {
+        Some(Vec3 {
+            x: self.add(a.x, b.x)?,
+            y: self.add(a.y, b.y)?,
+            z: self.add(a.z, b.z)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverflowError {
+    pub body: BodyId,
+}
+
+impl fmt::Display for OverflowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "integer overflow while updating body {}", self.body)
+    }
+}
+
+impl Error for OverflowError {}
+
+/// Returned by `System::run_monitored`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MonitorError {
+    /// Total momentum moved further than the tolerance.
+    Drift {
+        /// The step (counting from 1) after which the drift was seen.
+        step: usize,
+        /// Manhattan distance between the initial and current momentum.
+        drift: usize,
+    },
+    /// A speed limit or a fixed body legitimately changes momentum, so
+    /// there's nothing to monitor.
+    NotConserved,
+}
+
+impl fmt::Display for MonitorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Drift { step, drift } => {
+                write!(f, "momentum drifted by {} after step {}", drift, step)
+            }
+            Self::NotConserved => write!(
+                f,
+                "momentum isn't conserved with a speed limit or fixed bodies"
+            ),
+        }
+    }
+}
+
+impl Error for MonitorError {}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub const ALL: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+}
+
+impl<T: Copy> Vec3<T> {
+    pub fn component(&self, axis: Axis) -> T {
+        match axis {
+            Axis::X => self.x,
+            Axis::Y => self.y,
+            Axis::Z => self.z,
+        }
+    }
+
+    /// The components as `[x, y, z]`.
+    pub fn as_array(&self) -> [T; 3] {
+        [self.x, self.y, self.z]
+    }
+
+    pub fn from_array([x, y, z]: [T; 3]) -> Self {
+        Self { x, y, z }
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<T: nalgebra::Scalar> From<nalgebra::Vector3<T>> for Vec3<T> {
+    fn from(v: nalgebra::Vector3<T>) -> Self {
+        let [[x, y, z]] = v.data.0;
+        Self { x, y, z }
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<T: nalgebra::Scalar> From<Vec3<T>> for nalgebra::Vector3<T> {
+    fn from(v: Vec3<T>) -> Self {
+        Self::new(v.x, v.y, v.z)
+    }
+}
+
+impl<T: AddAssign> AddAssign for Vec3<T> {
+    fn add_assign(&mut self, other: Self) {
+        self.x += other.x;
+        self.y += other.y;
+        self.z += other.z;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Body {
+    pub position: Position,
+    pub velocity: Velocity,
+    group: usize,
+    fixed: bool,
+}
+
+impl Body {
+    pub fn new(position: Position) -> Self {
+        Self::with_velocity(position, Velocity::new())
+    }
+
+    pub fn with_velocity(position: Position, velocity: Velocity) -> Self {
+        Self {
+            position,
+            velocity,
+            group: 0,
+            fixed: false,
+        }
+    }
+
+    /// Bodies only attract others in the same group; all start in group 0.
+    pub fn group(&self) -> usize {
+        self.group
+    }
+
+    /// Fixed bodies still attract others but never move themselves.
+    pub fn is_fixed(&self) -> bool {
+        self.fixed
+    }
+
+    /// Velocity change `other` induces in this body, scaled by `weight`.
+    fn calc_gravity(&self, other: &Self, weight: f64) -> Velocity {
+        let pull = |mine: isize, theirs: isize| {
+            let pull = theirs.cmp(&mine) as isize;
+            if weight == 1.0 {
+                pull
+            } else {
+                round(pull as f64 * weight)
+            }
+        };
+        Velocity {
+            x: pull(self.position.x, other.position.x),
+            y: pull(self.position.y, other.position.y),
+            z: pull(self.position.z, other.position.z),
+        }
+    }
+
+    /// Accumulates a velocity change into this body.
+    pub fn apply_impulse(&mut self, delta: Velocity) {
+        self.velocity += delta;
+    }
+
+    /// The energies saturate at `usize::MAX`, which only matters for the
+    /// extreme states `OverflowPolicy::Saturating` and `Wrapping` can reach.
+    pub fn potential_energy(&self) -> usize {
+        BodyN::from(self).potential_energy()
+    }
+
+    pub fn kinetic_energy(&self) -> usize {
+        BodyN::from(self).kinetic_energy()
+    }
+
+    pub fn total_energy(&self) -> usize {
+        BodyN::from(self).total_energy()
+    }
+}
+
+// `f64::round` isn't available without std.
+fn round(x: f64) -> isize {
+    if x < 0.0 {
+        (x - 0.5) as isize
+    } else {
+        (x + 0.5) as isize
+    }
+}
+
+/// Index of a body within its `System`.
+pub type BodyId = usize;
+
+/// Read-only view of a body that doesn't expose how the `System` stores it.
+#[derive(Debug)]
+pub struct BodyView<'a>(&'a Body);
+
+impl<'a> BodyView<'a> {
+    pub fn position(&self) -> Position {
+        self.0.position
+    }
+
+    pub fn velocity(&self) -> Velocity {
+        self.0.velocity
+    }
+
+    pub fn group(&self) -> usize {
+        self.0.group
+    }
+
+    pub fn is_fixed(&self) -> bool {
+        self.0.fixed
+    }
+
+    pub fn potential_energy(&self) -> usize {
+        self.0.potential_energy()
+    }
+
+    pub fn kinetic_energy(&self) -> usize {
+        self.0.kinetic_energy()
+    }
+
+    pub fn total_energy(&self) -> usize {
+        self.0.total_energy()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InteractionMatrixError {
+    WrongSize { expected: usize, actual: usize },
+    NotSquare { row: usize },
+    NotFinite { row: usize, col: usize },
+    NotSymmetric { row: usize, col: usize },
+}
+
+impl fmt::Display for InteractionMatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::WrongSize { expected, actual } => write!(
+                f,
+                "interaction matrix has {} rows but the system has {} bodies",
+                actual, expected
+            ),
+            Self::NotSquare { row } => {
+                write!(f, "interaction matrix row {} has the wrong length", row)
+            }
+            Self::NotFinite { row, col } => write!(
+                f,
+                "interaction matrix entry ({}, {}) isn't finite",
+                row, col
+            ),
+            Self::NotSymmetric { row, col } => write!(
+                f,
+                "interaction matrix entries ({0}, {1}) and ({1}, {0}) differ",
+                row, col
+            ),
+        }
+    }
+}
+
+impl Error for InteractionMatrixError {}
+
+/// How the axis periods relate; see `System::subcycle_structure`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubcycleInfo {
+    /// Period of each axis, in `Axis::ALL` order.
+    pub periods: [usize; 3],
+    /// Pairs `(a, b)` of distinct axes where the period of `a` divides the
+    /// period of `b`, so `a` repeats a whole number of times within `b`.
+    pub divides: Vec<(Axis, Axis)>,
+}
+
+/// Watches the energies a run produces and flags when they grew at every
+/// one of the last `window` steps, by more than `threshold` overall, so a
+/// driver can stop a run that's flying apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DivergenceDetector {
+    window: usize,
+    threshold: usize,
+    energies: VecDeque<usize>,
+}
+
+impl DivergenceDetector {
+    pub fn new(window: usize, threshold: usize) -> Self {
+        Self {
+            window,
+            threshold,
+            energies: VecDeque::with_capacity(window + 1),
+        }
+    }
+
+    /// Records the latest `total_energy` and reports whether the run is
+    /// diverging. Feed it the starting energy and then one value per step.
+    pub fn observe(&mut self, energy: usize) -> bool {
+        if self.energies.len() > self.window {
+            self.energies.pop_front();
+        }
+        self.energies.push_back(energy);
+        if self.energies.len() <= self.window {
+            return false;
+        }
+        let rising = self
+            .energies
+            .iter()
+            .zip(self.energies.iter().skip(1))
+            .all(|(a, b)| b > a);
+        let first = self.energies[0];
+        rising && energy - first > self.threshold
+    }
+}
+
+/// How many steps `System::run` searches for each axis period before
+/// reporting that there is none.
+pub const PERIOD_SEARCH_LIMIT: usize = 1_000_000;
+
+/// Summary of a run, as emitted by the binary's `--json` mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RunResult {
+    /// Number of steps simulated before measuring `total_energy`.
+    pub energy_at_steps: usize,
+    pub total_energy: usize,
+    /// Steps until the whole system first returns to its initial state, or
+    /// `None` if an axis doesn't within `PERIOD_SEARCH_LIMIT` steps.
+    pub period: Option<u64>,
+}
+
+/// An immutable copy of a system's bodies, from `System::freeze`. Share it
+/// across threads and clone the `Arc` while the live system keeps stepping.
+#[derive(Debug, PartialEq)]
+pub struct FrozenState {
+    bodies: Vec<Body>,
+}
+
+impl FrozenState {
+    pub fn len(&self) -> usize {
+        self.bodies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bodies.is_empty()
+    }
+
+    pub fn body(&self, id: BodyId) -> Option<BodyView<'_>> {
+        self.bodies.get(id).map(BodyView)
+    }
+
+    pub fn bodies(&self) -> impl Iterator<Item = BodyView<'_>> {
+        self.bodies.iter().map(BodyView)
+    }
+
+    pub fn total_energy(&self) -> usize {
+        self.bodies
+            .iter()
+            .fold(0, |sum: usize, b| sum.saturating_add(b.total_energy()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct System {
+    bodies: Vec<Body>,
+    interactions: Option<Vec<Vec<f64>>>,
+    overflow: OverflowPolicy,
+    max_speed: Option<usize>,
+}
+
+impl FromIterator<Body> for System {
+    fn from_iter<I: IntoIterator<Item = Body>>(bodies: I) -> Self {
+        Self {
+            bodies: bodies.into_iter().collect(),
+            interactions: None,
+            overflow: OverflowPolicy::default(),
+            max_speed: None,
+        }
+    }
+}
+
+/// A source of starting bodies for `System::from_initial`.
+pub trait InitialConditions {
+    type Error;
+
+    fn bodies(self) -> Result<Vec<Body>, Self::Error>;
+}
+
+impl InitialConditions for Vec<Body> {
+    type Error = Infallible;
+
+    fn bodies(self) -> Result<Vec<Body>, Infallible> {
+        Ok(self)
+    }
+}
+
+/// Parses the `System::from_str` format.
+impl InitialConditions for &str {
+    type Error = ParseError;
+
+    fn bodies(self) -> Result<Vec<Body>, ParseError> {
+        Ok(self.parse::<System>()?.bodies)
+    }
+}
+
+/// Built-in starting configurations.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Preset {
+    /// See `System::aoc_example_1`.
+    AocExample1,
+    /// See `System::aoc_example_2`.
+    AocExample2,
+}
+
+impl InitialConditions for Preset {
+    type Error = Infallible;
+
+    fn bodies(self) -> Result<Vec<Body>, Infallible> {
+        let system = match self {
+            Self::AocExample1 => System::aoc_example_1(),
+            Self::AocExample2 => System::aoc_example_2(),
+        };
+        Ok(system.bodies)
+    }
+}
+
+impl System {
+    pub fn new(positions: Vec<Position>) -> Self {
+        Self::new_with_velocities(
+            positions
+                .into_iter()
+                .map(|p| (p, Velocity::new()))
+                .collect(),
+        )
+    }
+
+    /// Builds a system from any source of initial conditions.
+    pub fn from_initial<I: InitialConditions>(initial: I) -> Result<Self, I::Error> {
+        Ok(initial.bodies()?.into_iter().collect())
+    }
+
+    /// The first example system from Advent of Code 2019 day 12.
+    pub fn aoc_example_1() -> Self {
+        Self::new(vec![
+            Position { x: -1, y: 0, z: 2 },
+            Position {
+                x: 2,
+                y: -10,
+                z: -7,
+            },
+            Position { x: 4, y: -8, z: 8 },
+            Position { x: 3, y: 5, z: -1 },
+        ])
+    }
+
+    /// The second example system from Advent of Code 2019 day 12.
+    pub fn aoc_example_2() -> Self {
+        Self::new(vec![
+            Position {
+                x: -8,
+                y: -10,
+                z: 0,
+            },
+            Position { x: 5, y: 5, z: 10 },
+            Position { x: 2, y: -7, z: 3 },
+            Position { x: 9, y: -8, z: -3 },
+        ])
+    }
+
+    pub fn new_with_velocities(initial: Vec<(Position, Velocity)>) -> Self {
+        initial
+            .into_iter()
+            .map(|(position, velocity)| Body::with_velocity(position, velocity))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bodies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bodies.is_empty()
+    }
+
+    pub fn body(&self, id: BodyId) -> Option<BodyView<'_>> {
+        self.bodies.get(id).map(BodyView)
+    }
+
+    pub fn bodies(&self) -> impl Iterator<Item = BodyView<'_>> {
+        self.bodies.iter().map(BodyView)
+    }
+
+    /// Snapshots the current bodies into a shareable, read-only state.
+    pub fn freeze(&self) -> Arc<FrozenState> {
+        Arc::new(FrozenState {
+            bodies: self.bodies.clone(),
+        })
+    }
+
+    /// Deep-clones the system with `body`'s position nudged by `delta`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `body` is out of range.
+    pub fn perturbed_clone(&self, body: BodyId, delta: Vec3) -> System {
+        let mut clone = self.clone();
+        clone.bodies[body].position += delta;
+        clone
+    }
+
+    /// Velocity of `a` relative to `b`, or `None` if either id is invalid.
+    pub fn relative_velocity(&self, a: BodyId, b: BodyId) -> Option<Velocity> {
+        Some(self.body(a)?.velocity() - self.body(b)?.velocity())
+    }
+
+    /// Position of `a` relative to `b`, or `None` if either id is invalid.
+    pub fn relative_position(&self, a: BodyId, b: BodyId) -> Option<Position> {
+        Some(self.body(a)?.position() - self.body(b)?.position())
+    }
+
+    /// Time until `a` and `b` are nearest, assuming straight-line motion:
+    /// `-(dr·dv)/(dv·dv)`. A negative result means they're already
+    /// separating and is returned as-is. `None` if either id is invalid or
+    /// the bodies have no relative velocity.
+    pub fn time_to_closest(&self, a: BodyId, b: BodyId) -> Option<f64> {
+        let dr = self.relative_position(a, b)?;
+        let dv = self.relative_velocity(a, b)?;
+        let dot = |u: Vec3, v: Vec3| {
+            u.x as f64 * v.x as f64 + u.y as f64 * v.y as f64 + u.z as f64 * v.z as f64
+        };
+        let speed_sq = dot(dv, dv);
+        if speed_sq == 0.0 {
+            return None;
+        }
+        Some(-dot(dr, dv) / speed_sq)
+    }
+
+    /// Folds `f` over every body in id order.
+    pub fn fold_bodies<B, F: FnMut(B, &Body) -> B>(&self, init: B, f: F) -> B {
+        self.bodies.iter().fold(init, f)
+    }
+
+    /// Appends all of `other`'s bodies, keeping their positions and velocities
+    /// as-is.
+    ///
+    /// If either system has an interaction matrix, each keeps its own weights
+    /// and pairs spanning the two interact with weight 1.
+    pub fn merge(&mut self, other: System) {
+        if self.interactions.is_some() || other.interactions.is_some() {
+            let (n, m) = (self.len(), other.len());
+            let matrix = (0..n + m)
+                .map(|i| {
+                    (0..n + m)
+                        .map(|j| match (i < n, j < n) {
+                            (true, true) => self.weight(i, j),
+                            (false, false) => other.weight(i - n, j - n),
+                            _ => 1.0,
+                        })
+                        .collect()
+                })
+                .collect();
+            self.interactions = Some(matrix);
+        }
+        self.bodies.extend(other.bodies);
+    }
+
+    /// Sets an N×N symmetric matrix of finite weights scaling the pull
+    /// between each pair of bodies. The per-axis pull is multiplied by the
+    /// weight and rounded half away from zero, so 1 leaves the pair
+    /// unchanged, 2 doubles it, and any weight strictly between -0.5 and 0.5
+    /// disables the pair.
+    pub fn set_interaction_matrix(
+        &mut self,
+        matrix: Vec<Vec<f64>>,
+    ) -> Result<(), InteractionMatrixError> {
+        if matrix.len() != self.len() {
+            return Err(InteractionMatrixError::WrongSize {
+                expected: self.len(),
+                actual: matrix.len(),
+            });
+        }
+        if let Some(row) = matrix.iter().position(|r| r.len() != matrix.len()) {
+            return Err(InteractionMatrixError::NotSquare { row });
+        }
+        for (row, weights) in matrix.iter().enumerate() {
+            if let Some(col) = weights.iter().position(|w| !w.is_finite()) {
+                return Err(InteractionMatrixError::NotFinite { row, col });
+            }
+        }
+        for (row, col) in (0..matrix.len()).tuple_combinations() {
+            if matrix[row][col] != matrix[col][row] {
+                return Err(InteractionMatrixError::NotSymmetric { row, col });
+            }
+        }
+        self.interactions = Some(matrix);
+        Ok(())
+    }
+
+    fn weight(&self, i: BodyId, j: BodyId) -> f64 {
+        matrix_weight(&self.interactions, i, j)
+    }
+
+    /// Puts a body in an interaction group. Gravity only acts between bodies
+    /// in the same group, so separate groups evolve independently.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is out of range.
+    pub fn set_group(&mut self, id: BodyId, group: usize) {
+        self.bodies[id].group = group;
+    }
+
+    /// Pins a body in place: it keeps pulling on the others, but its own
+    /// velocity changes are discarded and it doesn't move. Momentum is no
+    /// longer conserved while a body is fixed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is out of range.
+    pub fn fix_body(&mut self, id: BodyId) {
+        self.bodies[id].fixed = true;
+    }
+
+    /// Releases a body pinned by `fix_body`; it resumes with the velocity it
+    /// had when it was fixed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is out of range.
+    pub fn unfix_body(&mut self, id: BodyId) {
+        self.bodies[id].fixed = false;
+    }
+
+    /// Effective weight of a pair after both groups and the interaction matrix.
+    fn coupling(&self, i: BodyId, j: BodyId) -> f64 {
+        if self.bodies[i].group != self.bodies[j].group {
+            0.0
+        } else {
+            self.weight(i, j)
+        }
+    }
+
+    /// Adds `offset` to every body's position; velocities are unaffected.
+    pub fn translate(&mut self, offset: Vec3) {
+        self.bodies.iter_mut().for_each(|b| b.position += offset)
+    }
+
+    /// Multiplies every body's position by `factor`.
+    pub fn scale_positions(&mut self, factor: isize) {
+        for body in self.bodies.iter_mut() {
+            let position = &mut body.position;
+            position.x *= factor;
+            position.y *= factor;
+            position.z *= factor;
+        }
+    }
+
+    /// Multiplies every body's velocity by `factor`.
+    pub fn scale_velocities(&mut self, factor: isize) {
+        for body in self.bodies.iter_mut() {
+            let velocity = &mut body.velocity;
+            velocity.x *= factor;
+            velocity.y *= factor;
+            velocity.z *= factor;
+        }
+    }
+
+    /// Adds `delta` to every body's velocity, e.g. to give the whole system a
+    /// drift.
+    pub fn kick_all(&mut self, delta: Velocity) {
+        for body in self.bodies.iter_mut() {
+            body.apply_impulse(delta);
+        }
+    }
+
+    /// Adds `delta` to one body's velocity.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is out of range.
+    pub fn kick(&mut self, id: BodyId, delta: Velocity) {
+        self.bodies[id].apply_impulse(delta);
+    }
+
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.overflow = policy;
+    }
+
+    /// Clamps every velocity component to at most `limit` in magnitude after
+    /// gravity is applied and before bodies move. `None`, the default, leaves
+    /// velocities unbounded. Clamping makes the dynamics irreversible, so the
+    /// system may never return to its start; the period queries return `None`
+    /// rather than search for it.
+    pub fn set_max_speed(&mut self, limit: Option<usize>) {
+        self.max_speed = limit;
+    }
+
+    /// Applies one round of pairwise gravity and then moves every body. With
+    /// fewer than two bodies there are no pairs, so bodies simply drift.
+    ///
+    /// # Panics
+    ///
+    /// Panics on overflow under `OverflowPolicy::Checked`; use `try_step` to
+    /// handle it instead.
+    pub fn step(&mut self) {
+        if let Err(e) = self.try_step() {
+            panic!("{}", e);
+        }
+    }
+
+    /// Like `step`, but returns whether any position or velocity changed, so
+    /// a driver can stop once the system reaches a fixed point. Plain signum
+    /// dynamics are reversible, so only a system that starts at a fixed
+    /// point or is held by e.g. `set_max_speed` or `fix_body` stops changing.
+    ///
+    /// # Panics
+    ///
+    /// Panics on overflow under `OverflowPolicy::Checked`.
+    pub fn step_returns_changed(&mut self) -> bool {
+        let before = self.bodies.clone();
+        self.step();
+        self.bodies != before
+    }
+
+    /// Like `step`, but reports a `Checked` overflow as an error. The system
+    /// is left unchanged when that happens.
+    pub fn try_step(&mut self) -> Result<(), OverflowError> {
+        let deltas = self.compute_deltas();
+        apply(&mut self.bodies, &deltas, self.overflow, self.max_speed)
+    }
+
+    /// First half of a `step`: the velocity change gravity gives each body,
+    /// computed without touching the system and before any `set_max_speed`
+    /// clamp. Fixed bodies get zero; pairs pull equally and oppositely, so
+    /// otherwise these sum to zero. Pass the result to `apply_deltas` to
+    /// finish the step.
+    pub fn compute_deltas(&self) -> Vec<Velocity> {
+        let interactions = &self.interactions;
+        pair_deltas(&self.bodies, |i, j, a, b| {
+            a.calc_gravity(b, matrix_weight(interactions, i, j))
+        })
+    }
+
+    /// Second half of a `step`: adds `deltas[id]` to each body's velocity,
+    /// applies any `set_max_speed` clamp, then moves every body.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `deltas` doesn't have one entry per body, or on overflow
+    /// under `OverflowPolicy::Checked`.
+    pub fn apply_deltas(&mut self, deltas: &[Velocity]) {
+        assert_eq!(deltas.len(), self.len(), "expected one delta per body");
+        if let Err(e) = apply(&mut self.bodies, deltas, self.overflow, self.max_speed) {
+            panic!("{}", e);
+        }
+    }
+
+    /// The pair whose gravity changes velocity the most this step, by the
+    /// Manhattan size of the pull. Ties go to the closer pair (Manhattan
+    /// distance). `None` if no pair pulls at all.
+    pub fn dominant_pair(&self) -> Option<(BodyId, BodyId)> {
+        (0..self.bodies.len())
+            .tuple_combinations()
+            .map(|(i, j)| {
+                let (a, b) = (&self.bodies[i], &self.bodies[j]);
+                let pull = VecN::from(a.calc_gravity(b, self.coupling(i, j))).manhattan();
+                let distance = VecN::from(a.position - b.position).manhattan();
+                (pull, Reverse(distance), Reverse((i, j)))
+            })
+            .filter(|&(pull, _, _)| pull > 0)
+            .max()
+            .map(|(_, _, Reverse(pair))| pair)
+    }
+
+    /// Steps the system with a custom pairwise law. `force(a, b)` is the
+    /// velocity change for `a`; `b` receives its negation, so momentum is
+    /// conserved. Groups still apply, but the interaction matrix only scales
+    /// the built-in gravity and is ignored here.
+    ///
+    /// # Panics
+    ///
+    /// Panics on overflow under `OverflowPolicy::Checked`.
+    pub fn step_with_force<F>(&mut self, force: F)
+    where
+        F: Fn(&Body, &Body) -> Velocity,
+    {
+        let force = |_, _, a: &Body, b: &Body| force(a, b);
+        if let Err(e) = advance(&mut self.bodies, force, self.overflow, self.max_speed) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Steps until `predicate` holds, returning the number of steps taken, or
+    /// `None` if it hasn't held after `max_steps`.
+    pub fn step_until<F>(&mut self, mut predicate: F, max_steps: usize) -> Option<usize>
+    where
+        F: FnMut(&Self) -> bool,
+    {
+        for count in 1..=max_steps {
+            self.step();
+            if count % 100 == 0 {
+                debug!("step {}: total energy {}", count, self.total_energy());
+            }
+            if predicate(self) {
+                info!("stopping condition met after {} steps", count);
+                return Some(count);
+            }
+        }
+        None
+    }
+
+    /// Steps up to `steps` times, calling `callback(step, self)` after each
+    /// step (counting from 1). Stops early when the callback returns `false`.
+    /// Returns the number of steps taken.
+    pub fn step_with_callback<F>(&mut self, steps: usize, mut callback: F) -> usize
+    where
+        F: FnMut(usize, &Self) -> bool,
+    {
+        for count in 1..=steps {
+            self.step();
+            if !callback(count, self) {
+                return count;
+            }
+        }
+        steps
+    }
+
+    /// Keeps stepping until `budget` of wall-clock time has passed and returns
+    /// the number of steps taken. The clock is only read every few steps, so
+    /// this may overrun by a handful of steps and always takes at least that
+    /// many.
+    #[cfg(feature = "std")]
+    pub fn step_for(&mut self, budget: Duration) -> usize {
+        const CLOCK_CHECK_INTERVAL: usize = 16;
+        let start = Instant::now();
+        let mut steps = 0;
+        loop {
+            for _ in 0..CLOCK_CHECK_INTERVAL {
+                self.step();
+            }
+            steps += CLOCK_CHECK_INTERVAL;
+            if start.elapsed() >= budget {
+                return steps;
+            }
+        }
+    }
+
+    /// Steps `steps` times, checking after every step that total momentum is
+    /// within `tol` (Manhattan distance) of where it started. Pairwise pulls
+    /// are equal and opposite, so any drift means something went wrong. The
+    /// energy `total_energy` measures isn't conserved by this model, so it
+    /// isn't checked.
+    ///
+    /// `set_max_speed` and `fix_body` change momentum on purpose, so with
+    /// either in effect this returns `MonitorError::NotConserved` without
+    /// stepping.
+    pub fn run_monitored(&mut self, steps: usize, tol: usize) -> Result<(), MonitorError> {
+        self.monitor(steps, tol, System::step)
+    }
+
+    fn monitor<S>(&mut self, steps: usize, tol: usize, mut step: S) -> Result<(), MonitorError>
+    where
+        S: FnMut(&mut Self),
+    {
+        if self.max_speed.is_some() || self.bodies.iter().any(|b| b.fixed) {
+            return Err(MonitorError::NotConserved);
+        }
+        let initial = self.momentum();
+        for count in 1..=steps {
+            step(self);
+            let drift = VecN::from(self.momentum() - initial).manhattan();
+            if drift > tol {
+                return Err(MonitorError::Drift { step: count, drift });
+            }
+        }
+        Ok(())
+    }
+
+    /// Uses Brent's algorithm to find how many distinct states are visited
+    /// before the system repeats, and the step at which the cycle starts.
+    ///
+    /// Returns `(max_steps, None)` if no repeat is found within `max_steps`.
+    pub fn count_unique_states(&self, max_steps: usize) -> (usize, Option<usize>) {
+        let mut power = 1;
+        let mut lambda = 1;
+        let mut tortoise = self.clone();
+        let mut hare = self.clone();
+        hare.step();
+        let mut steps = 1;
+        while tortoise != hare {
+            if steps >= max_steps {
+                return (max_steps, None);
+            }
+            if power == lambda {
+                tortoise = hare.clone();
+                power *= 2;
+                lambda = 0;
+            }
+            hare.step();
+            steps += 1;
+            lambda += 1;
+        }
+
+        let mut tortoise = self.clone();
+        let mut hare = self.clone();
+        for _ in 0..lambda {
+            hare.step();
+        }
+        let mut mu = 0;
+        while tortoise != hare {
+            tortoise.step();
+            hare.step();
+            mu += 1;
+        }
+        info!("found a cycle of length {} starting at step {}", lambda, mu);
+        (mu + lambda, Some(mu))
+    }
+
+    /// Finds the first repeated full state by remembering every state seen.
+    /// Returns `(start, length)` of the cycle, or `None` if no state repeats
+    /// within `max_steps`. Uses std's default hasher; see
+    /// `find_cycle_with_hasher` to pick another.
+    #[cfg(feature = "std")]
+    pub fn find_cycle(&self, max_steps: usize) -> Option<(usize, usize)> {
+        self.find_cycle_with_hasher(max_steps, RandomState::new())
+    }
+
+    /// Like `find_cycle`, but the set of seen states uses `hasher`.
+    #[cfg(feature = "std")]
+    pub fn find_cycle_with_hasher<S: BuildHasher>(
+        &self,
+        max_steps: usize,
+        hasher: S,
+    ) -> Option<(usize, usize)> {
+        let mut seen = HashMap::with_hasher(hasher);
+        let mut system = self.clone();
+        seen.insert(system.state(), 0);
+        for step in 1..=max_steps {
+            system.step();
+            if let Some(start) = seen.insert(system.state(), step) {
+                return Some((start, step - start));
+            }
+        }
+        None
+    }
+
+    /// Number of steps for each axis (in `Axis::ALL` order) to return to its
+    /// current positions and velocities, or `None` if some axis hasn't come
+    /// back within `max_steps`. A speed limit makes the dynamics
+    /// irreversible, and a group with nonzero momentum on an axis drifts off
+    /// forever, so both return `None` without stepping.
+    pub fn axis_periods(&self, max_steps: usize) -> Option<[usize; 3]> {
+        if self.max_speed.is_some() || self.drifts() {
+            return None;
+        }
+        let initial: Vec<Vec<(isize, isize)>> = Axis::ALL
+            .iter()
+            .map(|&axis| self.axis_state(axis))
+            .collect();
+        let mut periods = [0; 3];
+        let mut system = self.clone();
+        for steps in 1..=max_steps {
+            system.step();
+            for (i, &axis) in Axis::ALL.iter().enumerate() {
+                if periods[i] == 0 && system.axis_matches(axis, &initial[i]) {
+                    periods[i] = steps;
+                }
+            }
+            if !periods.contains(&0) {
+                return Some(periods);
+            }
+        }
+        None
+    }
+
+    /// Whether some group has nonzero momentum and no fixed body to hold it
+    /// back, so its center moves away and never returns.
+    fn drifts(&self) -> bool {
+        self.bodies.iter().any(|body| {
+            let group = || self.bodies.iter().filter(|b| b.group == body.group);
+            let momentum = group().fold(Velocity::new(), |mut sum, b| {
+                sum += b.velocity;
+                sum
+            });
+            !group().any(|b| b.fixed) && momentum != Velocity::new()
+        })
+    }
+
+    /// Steps until the whole system first returns to its current state: the
+    /// least common multiple of the axis periods. Plain systems are split
+    /// into an `Axis1D` per axis; groups, fixed bodies or interaction weights
+    /// fall back to stepping the whole system. `None` in the same cases as
+    /// `axis_periods`.
+    pub fn find_period(&self, max_steps: usize) -> Option<u64> {
+        if self.drifts() {
+            return None;
+        }
+        let periods = if self.is_plain() {
+            let mut periods = [0; 3];
+            for (period, &axis) in periods.iter_mut().zip(Axis::ALL.iter()) {
+                *period = self.axis(axis).find_period(max_steps)?;
+            }
+            periods
+        } else {
+            self.axis_periods(max_steps)?
+        };
+        Some(
+            periods
+                .iter()
+                .fold(1, |period, &axis| lcm(period, axis as u64)),
+        )
+    }
+
+    /// Whether `step` is exactly per-axis signum gravity with checked
+    /// arithmetic, as in `Axis1D`.
+    fn is_plain(&self) -> bool {
+        self.interactions.is_none()
+            && self.max_speed.is_none()
+            && self.overflow == OverflowPolicy::Checked
+            && self
+                .bodies
+                .iter()
+                .all(|b| !b.fixed && b.group == self.bodies[0].group)
+    }
+
+    /// Runs a copy of the system for `steps` steps and summarizes it.
+    pub fn run(&self, steps: usize) -> RunResult {
+        let mut system = self.clone();
+        for _ in 0..steps {
+            system.step();
+        }
+        RunResult {
+            energy_at_steps: steps,
+            total_energy: system.total_energy(),
+            period: self.find_period(PERIOD_SEARCH_LIMIT),
+        }
+    }
+
+    /// The `state()` the system will have after `n` steps, without simulating
+    /// all of them: each axis only needs to be advanced by `n` modulo its
+    /// period. If the axis periods aren't all found within `n` steps, the
+    /// system is simply stepped `n` times.
+    pub fn state_at(&self, n: u64) -> Vec<isize> {
+        let bound = usize::try_from(n).unwrap_or(usize::MAX);
+        let periods = match self.axis_periods(bound) {
+            Some(periods) => periods,
+            None => {
+                let mut system = self.clone();
+                for _ in 0..n {
+                    system.step();
+                }
+                return system.state();
+            }
+        };
+        let mut targets = [0; 3];
+        for (target, &period) in targets.iter_mut().zip(periods.iter()) {
+            *target = (n % period as u64) as usize;
+        }
+        let mut state = self.state();
+        let mut system = self.clone();
+        for step in 1..=targets.iter().copied().max().unwrap_or(0) {
+            system.step();
+            for (k, &axis) in Axis::ALL.iter().enumerate() {
+                if targets[k] != step {
+                    continue;
+                }
+                for (b, body) in system.bodies.iter().enumerate() {
+                    state[6 * b + k] = body.position.component(axis);
+                    state[6 * b + 3 + k] = body.velocity.component(axis);
+                }
+            }
+        }
+        state
+    }
+
+    /// Reports the axis periods and which of them divide one another. Every
+    /// axis repeats within the overall period, which is why the LCM of the
+    /// axis periods gives it. `None` in the same cases as `axis_periods`.
+    pub fn subcycle_structure(&self, max_steps: usize) -> Option<SubcycleInfo> {
+        let periods = self.axis_periods(max_steps)?;
+        let mut divides = Vec::new();
+        for (i, &a) in Axis::ALL.iter().enumerate() {
+            for (j, &b) in Axis::ALL.iter().enumerate() {
+                if i != j && periods[j].is_multiple_of(periods[i]) {
+                    divides.push((a, b));
+                }
+            }
+        }
+        Some(SubcycleInfo { periods, divides })
+    }
+
+    /// The axis with the longest period, which dominates the overall period.
+    /// `None` in the same cases as `axis_periods`.
+    pub fn limiting_axis(&self, max_steps: usize) -> Option<Axis> {
+        let periods = self.axis_periods(max_steps)?;
+        let mut limiting = 0;
+        for i in 1..periods.len() {
+            if periods[i] > periods[limiting] {
+                limiting = i;
+            }
+        }
+        Some(Axis::ALL[limiting])
+    }
+
+    fn axis_state(&self, axis: Axis) -> Vec<(isize, isize)> {
+        self.bodies
+            .iter()
+            .map(|b| (b.position.component(axis), b.velocity.component(axis)))
+            .collect()
+    }
+
+    fn axis_matches(&self, axis: Axis, state: &[(isize, isize)]) -> bool {
+        self.bodies
+            .iter()
+            .zip(state)
+            .all(|(b, &(p, v))| b.position.component(axis) == p && b.velocity.component(axis) == v)
+    }
+
+    pub fn total_energy(&self) -> usize {
+        self.bodies
+            .iter()
+            .fold(0, |sum: usize, b| sum.saturating_add(b.total_energy()))
+    }
+
+    /// Sum of all velocities (every body has unit mass).
+    pub fn momentum(&self) -> Velocity {
+        self.bodies.iter().fold(Velocity::new(), |mut sum, body| {
+            sum += body.velocity;
+            sum
+        })
+    }
+
+    /// Moment of inertia tensor about the center of mass, with every body
+    /// having unit mass. All zero for an empty system.
+    pub fn inertia_tensor(&self) -> [[f64; 3]; 3] {
+        let mut tensor = [[0.0; 3]; 3];
+        if self.bodies.is_empty() {
+            return tensor;
+        }
+        let n = self.bodies.len() as f64;
+        let mut center = [0.0; 3];
+        for body in self.bodies.iter() {
+            for (c, p) in center.iter_mut().zip(body.position.as_array()) {
+                *c += p as f64 / n;
+            }
+        }
+        for body in self.bodies.iter() {
+            let mut r = [0.0; 3];
+            for ((r, p), c) in r.iter_mut().zip(body.position.as_array()).zip(center) {
+                *r = p as f64 - c;
+            }
+            let r2: f64 = r.iter().map(|x| x * x).sum();
+            for (i, row) in tensor.iter_mut().enumerate() {
+                for (j, entry) in row.iter_mut().enumerate() {
+                    let diagonal = if i == j { r2 } else { 0.0 };
+                    *entry += diagonal - r[i] * r[j];
+                }
+            }
+        }
+        tensor
+    }
+
+    /// Average per-body kinetic energy, a proxy for the system's temperature.
+    /// Zero for an empty system.
+    pub fn mean_kinetic_energy(&self) -> f64 {
+        if self.bodies.is_empty() {
+            return 0.0;
+        }
+        let total = self
+            .bodies
+            .iter()
+            .fold(0, |sum: usize, b| sum.saturating_add(b.kinetic_energy()));
+        total as f64 / self.bodies.len() as f64
+    }
+
+    /// Each body's share of `total_energy`, keyed by its id.
+    pub fn energy_breakdown(&self) -> Vec<(BodyId, usize)> {
+        self.bodies
+            .iter()
+            .map(|b| b.total_energy())
+            .enumerate()
+            .collect()
+    }
+
+    /// True when every body's Manhattan speed is below `velocity_threshold`.
+    pub fn is_quiescent(&self, velocity_threshold: usize) -> bool {
+        self.bodies
+            .iter()
+            .all(|b| b.kinetic_energy() < velocity_threshold)
+    }
+
+    /// Graphviz DOT graph with an edge between every interacting pair of
+    /// bodies within Euclidean distance `radius` of each other. A pair
+    /// interacts if it's in one group and its weight doesn't round the pull
+    /// down to zero, as `compute_deltas` does.
+    pub fn to_dot(&self, radius: f64) -> String {
+        let mut dot = String::from("graph {\n");
+        for id in 0..self.bodies.len() {
+            writeln!(dot, "    {};", id).unwrap();
+        }
+        for (i, j) in (0..self.bodies.len()).tuple_combinations() {
+            let a = self.bodies[i].position;
+            let b = self.bodies[j].position;
+            let distance_sq: f64 = [(a.x, b.x), (a.y, b.y), (a.z, b.z)]
+                .iter()
+                .map(|&(p, q)| (p as f64 - q as f64) * (p as f64 - q as f64))
+                .sum();
+            if round(self.coupling(i, j)) != 0 && distance_sq <= radius * radius {
+                writeln!(dot, "    {} -- {};", i, j).unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Aligned plain-text table of every body's position, velocity and
+    /// energy, with numbers right-aligned in their columns.
+    pub fn to_table(&self) -> String {
+        const HEADER: [&str; 8] = ["body", "x", "y", "z", "vx", "vy", "vz", "energy"];
+        let mut rows: Vec<Vec<String>> = vec![HEADER.iter().map(|h| h.to_string()).collect()];
+        rows.extend(self.bodies.iter().enumerate().map(|(id, b)| {
+            let (p, v) = (b.position, b.velocity);
+            [id as isize, p.x, p.y, p.z, v.x, v.y, v.z]
+                .iter()
+                .map(|n| n.to_string())
+                .chain(Some(b.total_energy().to_string()))
+                .collect()
+        }));
+        let widths: Vec<usize> = (0..HEADER.len())
+            .map(|col| rows.iter().map(|row| row[col].len()).max().unwrap_or(0))
+            .collect();
+
+        let mut table = String::new();
+        for row in rows.iter() {
+            for (k, (cell, &width)) in row.iter().zip(widths.iter()).enumerate() {
+                let separator = if k == 0 { "" } else { "  " };
+                write!(table, "{}{:>width$}", separator, cell, width = width).unwrap();
+            }
+            table.push('\n');
+        }
+        table
+    }
+
+    pub fn state(&self) -> Vec<isize> {
+        let mut vec = Vec::new();
+        for body in self.bodies.iter() {
+            vec.push(body.position.x);
+            vec.push(body.position.y);
+            vec.push(body.position.z);
+            vec.push(body.velocity.x);
+            vec.push(body.velocity.y);
+            vec.push(body.velocity.z);
+        }
+        vec
+    }
+
+    /// Every body's position as `x, y, z` triples, in body order.
+    pub fn positions_flat(&self) -> Vec<isize> {
+        self.bodies
+            .iter()
+            .flat_map(|body| body.position.as_array())
+            .collect()
+    }
+
+    /// FNV-1a hash of every position and velocity, in `state()` order. Unlike
+    /// `state_hash` it's the same on every platform and build, so checksums
+    /// from separate runs can be compared step by step.
+    pub fn checksum(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for value in self.state() {
+            for byte in (value as i64).to_le_bytes().iter() {
+                hash = (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        hash
+    }
+
+    /// Hashes the full state without allocating, for cheap cycle detection.
+    #[cfg(feature = "std")]
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for body in self.bodies.iter() {
+            body.position.hash(&mut hasher);
+            body.velocity.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+/// Applies one step of signum gravity to an externally owned slice of
+/// bodies, scaling every pull by `gravity_scale`, then moves them. Bodies in
+/// different groups don't interact. `System::step` uses the same algorithm.
+///
+/// # Panics
+///
+/// Panics if a velocity or position overflows.
+pub fn step_bodies(bodies: &mut [Body], gravity_scale: isize) {
+    let scale = gravity_scale as f64;
+    let gravity = |_, _, a: &Body, b: &Body| a.calc_gravity(b, scale);
+    if let Err(e) = advance(bodies, gravity, OverflowPolicy::Checked, None) {
+        panic!("{}", e);
+    }
+}
+
+/// One step over `bodies` with a pairwise `force`; see `pair_deltas` and
+/// `apply`.
+fn advance<F>(
+    bodies: &mut [Body],
+    force: F,
+    overflow: OverflowPolicy,
+    max_speed: Option<usize>,
+) -> Result<(), OverflowError>
+where
+    F: Fn(BodyId, BodyId, &Body, &Body) -> Velocity,
+{
+    let deltas = pair_deltas(bodies, force);
+    apply(bodies, &deltas, overflow, max_speed)
+}
+
+/// Net velocity change of every body: `force(i, j, a, b)` is the change for
+/// `a`, and `b` gets its negation, for each pair in the same group. Fixed
+/// bodies get zero.
+fn pair_deltas<F>(bodies: &[Body], force: F) -> Vec<Velocity>
+where
+    F: Fn(BodyId, BodyId, &Body, &Body) -> Velocity,
+{
+    let mut deltas = vec![Velocity::new(); bodies.len()];
+    for (i, j) in (0..bodies.len()).tuple_combinations() {
+        if bodies[i].group != bodies[j].group {
+            continue;
+        }
+        let delta = force(i, j, &bodies[i], &bodies[j]);
+        deltas[i] += delta;
+        deltas[j] += -delta;
+    }
+    for (delta, body) in deltas.iter_mut().zip(bodies) {
+        if body.fixed {
+            *delta = Velocity::new();
+        }
+    }
+    deltas
+}
+
+/// Adds each delta to its body's velocity, clamps velocities to `max_speed`,
+/// then moves every body that isn't fixed. Nothing is written unless every
+/// body updates without overflowing.
+fn apply(
+    bodies: &mut [Body],
+    deltas: &[Velocity],
+    overflow: OverflowPolicy,
+    max_speed: Option<usize>,
+) -> Result<(), OverflowError> {
+    let limit = max_speed.map(|limit| limit.min(isize::MAX as usize) as isize);
+    let mut next = Vec::with_capacity(bodies.len());
+    for (id, (body, &delta)) in bodies.iter().zip(deltas).enumerate() {
+        if body.fixed {
+            next.push((body.position, body.velocity));
+            continue;
+        }
+        let mut velocity = overflow
+            .add_vec(body.velocity, delta)
+            .ok_or(OverflowError { body: id })?;
+        if let Some(limit) = limit {
+            velocity.x = velocity.x.clamp(-limit, limit);
+            velocity.y = velocity.y.clamp(-limit, limit);
+            velocity.z = velocity.z.clamp(-limit, limit);
+        }
+        let position = overflow
+            .add_vec(body.position, velocity)
+            .ok_or(OverflowError { body: id })?;
+        next.push((position, velocity));
+    }
+
+    for (body, (position, velocity)) in bodies.iter_mut().zip(next) {
+        body.position = position;
+        body.velocity = velocity;
+    }
+    Ok(())
+}
+
+fn lcm(a: u64, b: u64) -> u64 {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        let r = x % y;
+        x = y;
+        y = r;
+    }
+    a / x * b
+}
+
+fn matrix_weight(matrix: &Option<Vec<Vec<f64>>>, i: BodyId, j: BodyId) -> f64 {
+    matrix.as_ref().map_or(1.0, |m| m[i][j])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example1() {
+        let positions = vec![
+            Position { x: -1, y: 0, z: 2 },
+            Position {
+                x: 2,
+                y: -10,
+                z: -7,
+            },
+            Position { x: 4, y: -8, z: 8 },
+            Position { x: 3, y: 5, z: -1 },
+        ];
+        let mut system = System::new(positions);
+
+        for _ in 0..10 {
+            system.step()
+        }
+        assert_eq!(system.total_energy(), 179);
+    }
+
+    #[test]
+    fn example2() {
+        let positions = vec![
+            Position {
+                x: -8,
+                y: -10,
+                z: 0,
+            },
+            Position { x: 5, y: 5, z: 10 },
+            Position { x: 2, y: -7, z: 3 },
+            Position { x: 9, y: -8, z: -3 },
+        ];
+        let mut system = System::new(positions);
+
+        for _ in 0..100 {
+            system.step()
+        }
+        assert_eq!(system.total_energy(), 1940);
+    }
+
+    #[test]
+    fn aoc_examples() {
+        let mut system = System::aoc_example_1();
+        for _ in 0..10 {
+            system.step()
+        }
+        assert_eq!(system.total_energy(), 179);
+
+        let mut system = System::aoc_example_2();
+        for _ in 0..100 {
+            system.step()
+        }
+        assert_eq!(system.total_energy(), 1940);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn state_hash() {
+        let mut system = System::aoc_example_1();
+        let mut other = System::aoc_example_1();
+        assert_eq!(system.state_hash(), other.state_hash());
+
+        system.step();
+        assert_ne!(system.state_hash(), other.state_hash());
+
+        other.step();
+        assert_eq!(system.state_hash(), other.state_hash());
+    }
+
+    #[test]
+    fn rational_vec3() {
+        use num_rational::Rational64;
+
+        let mut position = Vec3 {
+            x: Rational64::from_integer(1),
+            y: Rational64::from_integer(0),
+            z: Rational64::from_integer(-2),
+        };
+        let velocity = Vec3 {
+            x: Rational64::new(1, 3),
+            y: Rational64::new(-1, 2),
+            z: Rational64::new(2, 7),
+        };
+        position += velocity;
+        position += velocity;
+        assert_eq!(
+            position,
+            Vec3 {
+                x: Rational64::new(5, 3),
+                y: Rational64::from_integer(-1),
+                z: Rational64::new(-10, 7),
+            }
+        );
+        let start = position - velocity - velocity;
+        assert_eq!(start.as_array(), [1, 0, -2].map(Rational64::from_integer));
+        assert_eq!((-velocity).component(Axis::Y), Rational64::new(1, 2));
+    }
+
+    #[test]
+    fn impulse_conserves_momentum() {
+        let mut a = Body::new(Position { x: 1, y: 2, z: 3 });
+        let mut b = Body::new(Position { x: -4, y: 0, z: 7 });
+        a.velocity = Velocity { x: 2, y: -1, z: 0 };
+
+        let momentum = |a: &Body, b: &Body| {
+            let mut total = a.velocity;
+            total += b.velocity;
+            total
+        };
+        let before = momentum(&a, &b);
+
+        let delta = Velocity { x: 3, y: -5, z: 1 };
+        a.apply_impulse(delta);
+        b.apply_impulse(-delta);
+
+        assert_eq!(a.velocity, Velocity { x: 5, y: -6, z: 1 });
+        assert_eq!(momentum(&a, &b), before);
+    }
+
+    #[test]
+    fn merge() {
+        let mut system = System::new(vec![
+            Position { x: -1, y: 0, z: 2 },
+            Position {
+                x: 2,
+                y: -10,
+                z: -7,
+            },
+        ]);
+        let mut other = System::new(vec![
+            Position { x: 4, y: -8, z: 8 },
+            Position { x: 3, y: 5, z: -1 },
+        ]);
+        system.step();
+        other.step();
+        let expected = system.total_energy() + other.total_energy();
+
+        system.merge(other);
+        assert_eq!(system.len(), 4);
+        assert_eq!(system.total_energy(), expected);
+    }
+
+    #[test]
+    fn translate_and_scale() {
+        let positions = vec![
+            Position { x: -1, y: 0, z: 2 },
+            Position {
+                x: 2,
+                y: -10,
+                z: -7,
+            },
+        ];
+        let offset = Vec3 { x: 5, y: -3, z: 1 };
+
+        let mut system = System::new(positions.clone());
+        system.step();
+        assert_eq!(
+            system.state(),
+            vec![0, -1, 1, 1, -1, -1, 1, -9, -6, -1, 1, 1]
+        );
+        system.translate(offset);
+        let state = system.state();
+        assert_eq!(state, vec![5, -4, 2, 1, -1, -1, 6, -12, -5, -1, 1, 1]);
+
+        // Gravity only depends on relative positions.
+        let mut translated = System::new(positions);
+        translated.translate(offset);
+        translated.step();
+        assert_eq!(translated.state(), state);
+
+        system.scale_positions(2);
+        system.scale_velocities(-1);
+        assert_eq!(
+            system.state(),
+            vec![10, -8, 4, -1, 1, 1, 12, -24, -10, 1, -1, -1]
+        );
+    }
+
+    #[test]
+    fn step_until() {
+        let mut naive = System::aoc_example_1();
+        let mut expected = 0;
+        while naive.total_energy() <= 150 {
+            naive.step();
+            expected += 1;
+        }
+
+        let mut system = System::aoc_example_1();
+        assert_eq!(
+            system.step_until(|s| s.total_energy() > 150, 100),
+            Some(expected)
+        );
+        assert_eq!(system.state(), naive.state());
+
+        let mut system = System::aoc_example_1();
+        assert_eq!(system.step_until(|_| false, 10), None);
+    }
+
+    #[test]
+    fn body_view() {
+        let mut system = System::aoc_example_1();
+        for _ in 0..10 {
+            system.step()
+        }
+
+        let body = system.body(0).unwrap();
+        assert_eq!(body.position(), Position { x: 2, y: 1, z: -3 });
+        assert_eq!(body.velocity(), Velocity { x: -3, y: -2, z: 1 });
+        assert_eq!(body.potential_energy(), 6);
+        assert_eq!(body.kinetic_energy(), 6);
+        assert_eq!(body.total_energy(), 36);
+
+        assert!(system.body(4).is_none());
+        let total: usize = system.bodies().map(|b| b.total_energy()).sum();
+        assert_eq!(total, 179);
+    }
+
+    #[test]
+    fn count_unique_states() {
+        let system = System::aoc_example_1();
+        assert_eq!(system.count_unique_states(10_000), (2772, Some(0)));
+        assert_eq!(system.count_unique_states(100), (100, None));
+    }
+
+    #[test]
+    fn energy_breakdown() {
+        let mut system = System::aoc_example_1();
+        for _ in 0..10 {
+            system.step()
+        }
+
+        let breakdown = system.energy_breakdown();
+        assert_eq!(breakdown, vec![(0, 36), (1, 45), (2, 80), (3, 18)]);
+        let total: usize = breakdown.iter().map(|&(_, e)| e).sum();
+        assert_eq!(total, system.total_energy());
+    }
+
+    #[test]
+    fn new_with_velocities() {
+        let positions = vec![
+            Position { x: -1, y: 0, z: 2 },
+            Position {
+                x: 2,
+                y: -10,
+                z: -7,
+            },
+        ];
+        let at_rest = positions.iter().map(|&p| (p, Velocity::new())).collect();
+        assert_eq!(
+            System::new_with_velocities(at_rest),
+            System::new(positions.clone())
+        );
+
+        let moving = vec![
+            (positions[0], Velocity { x: 1, y: 0, z: 0 }),
+            (positions[1], Velocity::new()),
+        ];
+        let mut system = System::new_with_velocities(moving);
+        let mut rest = System::new(positions);
+        system.step();
+        rest.step();
+        assert_eq!(
+            system.state(),
+            vec![1, -1, 1, 2, -1, -1, 1, -9, -6, -1, 1, 1]
+        );
+        assert_ne!(system.state(), rest.state());
+    }
+
+    #[test]
+    fn interaction_matrix() {
+        let positions = vec![
+            Position { x: 0, y: 0, z: 0 },
+            Position { x: 5, y: 5, z: 5 },
+            Position { x: -5, y: 0, z: 5 },
+        ];
+        let mut system = System::new(positions.clone());
+        assert_eq!(
+            system.set_interaction_matrix(vec![vec![0.0; 3]; 2]),
+            Err(InteractionMatrixError::WrongSize {
+                expected: 3,
+                actual: 2
+            })
+        );
+        assert_eq!(
+            system.set_interaction_matrix(vec![vec![0.0; 3], vec![0.0; 2], vec![0.0; 3]]),
+            Err(InteractionMatrixError::NotSquare { row: 1 })
+        );
+        let asymmetric = vec![
+            vec![0.0, 1.0, 1.0],
+            vec![0.0, 0.0, 1.0],
+            vec![1.0, 1.0, 0.0],
+        ];
+        assert_eq!(
+            system.set_interaction_matrix(asymmetric),
+            Err(InteractionMatrixError::NotSymmetric { row: 0, col: 1 })
+        );
+        let mut nan = vec![vec![1.0; 3]; 3];
+        nan[0][1] = f64::NAN;
+        nan[1][0] = f64::NAN;
+        assert_eq!(
+            system.set_interaction_matrix(nan),
+            Err(InteractionMatrixError::NotFinite { row: 0, col: 1 })
+        );
+        let mut infinite = vec![vec![1.0; 3]; 3];
+        infinite[2][2] = f64::INFINITY;
+        assert_eq!(
+            system.set_interaction_matrix(infinite),
+            Err(InteractionMatrixError::NotFinite { row: 2, col: 2 })
+        );
+
+        let matrix = vec![
+            vec![0.0, 0.0, 1.0],
+            vec![0.0, 0.0, 2.0],
+            vec![1.0, 2.0, 0.0],
+        ];
+        system.set_interaction_matrix(matrix).unwrap();
+        system.step();
+
+        // Bodies 0 and 1 ignore each other, body 2 pulls on both.
+        assert_eq!(
+            system.body(0).unwrap().velocity(),
+            Velocity { x: -1, y: 0, z: 1 }
+        );
+        assert_eq!(
+            system.body(1).unwrap().velocity(),
+            Velocity { x: -2, y: -2, z: 0 }
+        );
+        assert_eq!(
+            system.body(2).unwrap().velocity(),
+            Velocity { x: 3, y: 2, z: -1 }
+        );
+
+        let mut unweighted = System::new(positions);
+        unweighted.step();
+        assert_eq!(
+            unweighted.body(0).unwrap().velocity(),
+            Velocity { x: 0, y: 1, z: 2 }
+        );
+
+        let mut merged = System::new(vec![Position { x: 9, y: 9, z: 9 }]);
+        merged.merge(system);
+        assert_eq!(merged.weight(0, 1), 1.0);
+        assert_eq!(merged.weight(1, 2), 0.0);
+        assert_eq!(merged.weight(2, 3), 2.0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn logs_cycle() {
+        use log::{Log, Metadata, Record};
+        use std::sync::Mutex;
+
+        struct Capture(Mutex<Vec<String>>);
+
+        impl Log for Capture {
+            fn enabled(&self, _: &Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &Record) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+
+            fn flush(&self) {}
+        }
+
+        static LOGGER: Capture = Capture(Mutex::new(Vec::new()));
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Debug);
+
+        System::aoc_example_1().count_unique_states(10_000);
+
+        let messages = LOGGER.0.lock().unwrap();
+        assert!(messages
+            .iter()
+            .any(|m| m == "found a cycle of length 2772 starting at step 0"));
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[test]
+    fn no_std_core() {
+        // Only reached via `cargo test --no-default-features`.
+        let mut system = System::new(vec![
+            Position { x: -1, y: 0, z: 2 },
+            Position {
+                x: 2,
+                y: -10,
+                z: -7,
+            },
+        ]);
+        system.step();
+        assert_eq!(
+            system.state(),
+            vec![0, -1, 1, 1, -1, -1, 1, -9, -6, -1, 1, 1]
+        );
+    }
+
+    #[test]
+    fn is_quiescent() {
+        let mut isolated = System::new(vec![Position { x: 3, y: -2, z: 7 }]);
+        isolated.step();
+        assert!(isolated.is_quiescent(1));
+
+        let mut pair = System::new(vec![
+            Position { x: 0, y: 0, z: 0 },
+            Position { x: 4, y: 0, z: 0 },
+        ]);
+        assert!(pair.is_quiescent(1));
+        pair.step();
+        assert!(!pair.is_quiescent(1));
+        assert!(pair.is_quiescent(2));
+    }
+
+    #[test]
+    fn mean_kinetic_energy() {
+        assert_eq!(System::new(vec![]).mean_kinetic_energy(), 0.0);
+
+        let system = System::new_with_velocities(vec![
+            (Position::new(), Velocity { x: 1, y: -2, z: 0 }),
+            (Position::new(), Velocity { x: 0, y: 0, z: 4 }),
+            (Position::new(), Velocity::new()),
+            (Position::new(), Velocity { x: -1, y: 1, z: -1 }),
+        ]);
+        // (3 + 4 + 0 + 3) / 4
+        assert_eq!(system.mean_kinetic_energy(), 2.5);
+    }
+
+    #[test]
+    fn perturbed_clone() {
+        let mut system = System::new(vec![
+            Position { x: -1, y: 0, z: 2 },
+            Position {
+                x: 2,
+                y: -10,
+                z: -7,
+            },
+        ]);
+        system.step();
+        let original = system.state();
+
+        let delta = Vec3 { x: 1, y: 0, z: -3 };
+        let perturbed = system.perturbed_clone(1, delta);
+        assert_eq!(system.state(), original);
+
+        let mut expected = system.body(1).unwrap().position();
+        expected += delta;
+        assert_eq!(perturbed.body(1).unwrap().position(), expected);
+        assert_eq!(
+            perturbed.body(1).unwrap().velocity(),
+            system.body(1).unwrap().velocity()
+        );
+        assert_eq!(&perturbed.state()[..6], &original[..6]);
+
+        // The clone evolves independently.
+        let mut perturbed = perturbed;
+        perturbed.step();
+        assert_eq!(system.state(), original);
+    }
+
+    #[test]
+    fn limiting_axis() {
+        let system = System::aoc_example_1();
+        assert_eq!(system.axis_periods(100), Some([18, 28, 44]));
+        assert_eq!(system.axis_periods(43), None);
+        assert_eq!(system.limiting_axis(100), Some(Axis::Z));
+
+        // Only y varies, so x and z never move.
+        let system = System::new(vec![
+            Position { x: 1, y: -3, z: 0 },
+            Position { x: 1, y: 4, z: 0 },
+            Position { x: 1, y: 10, z: 0 },
+        ]);
+        let periods = system.axis_periods(1000).unwrap();
+        assert_eq!((periods[0], periods[2]), (1, 1));
+        assert!(periods[1] > 1);
+        assert_eq!(system.limiting_axis(1000), Some(Axis::Y));
+    }
+
+    #[test]
+    fn axis_periods_drift() {
+        let drifting = System::new_with_velocities(vec![
+            (Position::new(), Velocity::new()),
+            (Position { x: 3, y: 0, z: 0 }, Velocity { x: 0, y: 1, z: 0 }),
+        ]);
+        assert_eq!(drifting.axis_periods(usize::MAX), None);
+        assert_eq!(drifting.find_period(usize::MAX), None);
+        assert_eq!(drifting.limiting_axis(usize::MAX), None);
+        assert_eq!(drifting.subcycle_structure(usize::MAX), None);
+
+        let mut kicked = System::aoc_example_1();
+        kicked.kick(0, Velocity { x: 1, y: 0, z: 0 });
+        assert_eq!(kicked.axis_periods(usize::MAX), None);
+
+        // Opposite groups cancel out overall but each drifts on its own.
+        let mut grouped = System::new_with_velocities(vec![
+            (Position::new(), Velocity { x: 1, y: 0, z: 0 }),
+            (Position::new(), Velocity { x: -1, y: 0, z: 0 }),
+        ]);
+        grouped.set_group(1, 1);
+        assert_eq!(grouped.momentum(), Velocity::new());
+        assert_eq!(grouped.axis_periods(usize::MAX), None);
+
+        let mut naive = drifting.clone();
+        for _ in 0..50 {
+            naive.step();
+        }
+        assert_eq!(drifting.state_at(50), naive.state());
+    }
+
+    #[test]
+    fn six_bodies() {
+        let input = "<x=0, y=5, z=-3>\n\
+                     <x=3, y=-1, z=7>\n\
+                     <x=-4, y=2, z=0>\n\
+                     <x=8, y=9, z=-6>\n\
+                     <x=1, y=-7, z=4>\n\
+                     <x=-2, y=0, z=11>";
+        let mut system: System = input.parse().unwrap();
+        assert_eq!(system.len(), 6);
+
+        // Every coordinate is distinct, so each of the 15 pairs pulls on
+        // every axis and each body feels five unit pulls per axis.
+        system.step();
+        let velocities: Vec<Velocity> = system.bodies().map(|b| b.velocity()).collect();
+        assert_eq!(
+            velocities,
+            vec![
+                Velocity { x: 1, y: -3, z: 3 },
+                Velocity { x: -3, y: 3, z: -3 },
+                Velocity { x: 5, y: -1, z: 1 },
+                Velocity { x: -5, y: -5, z: 5 },
+                Velocity { x: -1, y: 5, z: -1 },
+                Velocity { x: 3, y: 1, z: -5 },
+            ]
+        );
+
+        for _ in 1..10 {
+            system.step();
+        }
+        assert_eq!(system.total_energy(), 903);
+    }
+
+    #[test]
+    fn empty_system() {
+        let mut system = System::new(vec![]);
+        for _ in 0..10 {
+            system.step();
+        }
+        assert!(system.is_empty());
+        assert_eq!(system.total_energy(), 0);
+        assert!(system.state().is_empty());
+    }
+
+    #[test]
+    fn single_body_drifts() {
+        let start = Position { x: 3, y: -2, z: 0 };
+        let velocity = Velocity { x: -1, y: 4, z: 2 };
+        let mut system = System::new_with_velocities(vec![(start, velocity)]);
+        for _ in 0..100 {
+            system.step();
+        }
+        let body = system.body(0).unwrap();
+        assert_eq!(body.velocity(), velocity);
+        assert_eq!(
+            body.position(),
+            Position {
+                x: -97,
+                y: 398,
+                z: 200
+            }
+        );
+    }
+
+    #[test]
+    fn overflow_policy() {
+        let near_max = Position {
+            x: isize::MAX - 1,
+            y: 0,
+            z: 0,
+        };
+        let velocity = Velocity { x: 3, y: 1, z: 0 };
+        let system = System::new_with_velocities(vec![(near_max, velocity)]);
+
+        let mut checked = system.clone();
+        assert_eq!(checked.try_step(), Err(OverflowError { body: 0 }));
+        assert_eq!(checked, system);
+
+        let mut saturating = system.clone();
+        saturating.set_overflow_policy(OverflowPolicy::Saturating);
+        saturating.step();
+        assert_eq!(
+            saturating.body(0).unwrap().position(),
+            Position {
+                x: isize::MAX,
+                y: 1,
+                z: 0
+            }
+        );
+        assert_eq!(saturating.total_energy(), usize::MAX);
+        assert_eq!(saturating.body(0).unwrap().potential_energy(), 1 << 63);
+        assert!(saturating.to_table().contains(&usize::MAX.to_string()));
+
+        let mut wrapping = system;
+        wrapping.set_overflow_policy(OverflowPolicy::Wrapping);
+        wrapping.step();
+        assert_eq!(
+            wrapping.body(0).unwrap().position(),
+            Position {
+                x: isize::MIN + 1,
+                y: 1,
+                z: 0
+            }
+        );
+        assert_eq!(
+            wrapping.body(0).unwrap().potential_energy(),
+            isize::MAX as usize + 1
+        );
+
+        // Velocities are guarded too, and the pull between distant bodies
+        // doesn't overflow.
+        let mut fast = System::new_with_velocities(vec![
+            (Position { x: -1, y: 0, z: 0 }, Velocity::new()),
+            (
+                Position {
+                    x: isize::MIN,
+                    y: 0,
+                    z: 0,
+                },
+                Velocity {
+                    x: isize::MAX,
+                    y: 0,
+                    z: 0,
+                },
+            ),
+        ]);
+        let before = fast.clone();
+        assert_eq!(fast.try_step(), Err(OverflowError { body: 1 }));
+        assert_eq!(fast, before);
+
+        // A later body overflowing leaves earlier bodies' moves unwritten.
+        let mut late = System::new_with_velocities(vec![
+            (Position::new(), Velocity { x: 1, y: 1, z: 1 }),
+            (near_max, velocity),
+        ]);
+        late.set_group(1, 1);
+        let before = late.clone();
+        assert_eq!(late.try_step(), Err(OverflowError { body: 1 }));
+        assert_eq!(late, before);
+    }
+
+    #[test]
+    fn to_dot() {
+        let mut system = System::new(vec![
+            Position { x: 0, y: 0, z: 0 },
+            Position { x: 3, y: 4, z: 0 },
+            Position { x: 0, y: 0, z: 2 },
+            Position { x: 20, y: 0, z: 0 },
+        ]);
+        assert_eq!(
+            system.to_dot(5.0),
+            "graph {\n    0;\n    1;\n    2;\n    3;\n    0 -- 1;\n    0 -- 2;\n}\n"
+        );
+        assert_eq!(system.to_dot(4.0).matches(" -- ").count(), 1);
+        assert_eq!(system.to_dot(100.0).matches(" -- ").count(), 6);
+
+        let mut matrix = vec![vec![1.0; 4]; 4];
+        matrix[0][1] = 0.0;
+        matrix[1][0] = 0.0;
+        system.set_interaction_matrix(matrix).unwrap();
+        assert!(!system.to_dot(5.0).contains("0 -- 1;"));
+        assert_eq!(system.to_dot(100.0).matches(" -- ").count(), 5);
+
+        // A weight of 0.4 rounds every pull to zero, so there's no edge.
+        let mut weak = System::new(vec![Position::new(), Position { x: 0, y: 0, z: 2 }]);
+        weak.set_interaction_matrix(vec![vec![1.0, 0.4], vec![0.4, 1.0]])
+            .unwrap();
+        assert_eq!(weak.compute_deltas(), vec![Velocity::new(); 2]);
+        assert_eq!(weak.dominant_pair(), None);
+        assert_eq!(weak.to_dot(5.0), "graph {\n    0;\n    1;\n}\n");
+    }
+
+    #[test]
+    fn relative_motion() {
+        let system = System::new_with_velocities(vec![
+            (
+                Position { x: -5, y: 1, z: 0 },
+                Velocity { x: 2, y: 0, z: 0 },
+            ),
+            (
+                Position { x: 5, y: 1, z: 0 },
+                Velocity { x: -3, y: 0, z: 1 },
+            ),
+        ]);
+        assert_eq!(
+            system.relative_velocity(0, 1),
+            Some(Velocity { x: 5, y: 0, z: -1 })
+        );
+        assert_eq!(
+            system.relative_position(0, 1),
+            Some(Position { x: -10, y: 0, z: 0 })
+        );
+        assert_eq!(
+            system.relative_position(1, 0),
+            Some(Position { x: 10, y: 0, z: 0 })
+        );
+        assert_eq!(system.relative_velocity(0, 2), None);
+        assert_eq!(system.relative_position(2, 0), None);
+    }
+
+    #[test]
+    fn time_to_closest() {
+        let system = System::new_with_velocities(vec![
+            (
+                Position { x: -5, y: 1, z: 0 },
+                Velocity { x: 2, y: 0, z: 0 },
+            ),
+            (
+                Position { x: 5, y: 1, z: 0 },
+                Velocity { x: -3, y: 0, z: 0 },
+            ),
+            (Position { x: 9, y: 0, z: 0 }, Velocity { x: 1, y: 0, z: 0 }),
+            (Position { x: 0, y: 4, z: 0 }, Velocity { x: 2, y: 0, z: 0 }),
+        ]);
+        // Head-on: 10 apart closing at 5 per step.
+        assert_eq!(system.time_to_closest(0, 1), Some(2.0));
+        assert_eq!(system.time_to_closest(1, 0), Some(2.0));
+        // 4 apart and separating at 4 per step.
+        assert_eq!(system.time_to_closest(1, 2), Some(-1.0));
+        // Same velocity: the distance never changes.
+        assert_eq!(system.time_to_closest(0, 3), None);
+        assert_eq!(system.time_to_closest(0, 4), None);
+    }
+
+    #[test]
+    fn fold_bodies() {
+        let mut system = System::aoc_example_1();
+        for _ in 0..10 {
+            system.step()
+        }
+        let max_kinetic = system.fold_bodies(0, |max, b| max.max(b.kinetic_energy()));
+        assert_eq!(max_kinetic, 8);
+
+        let moving_right = system.fold_bodies(0, |n, b| n + (b.velocity.x > 0) as usize);
+        assert_eq!(moving_right, 2);
+    }
+
+    #[test]
+    fn detect_divergence() {
+        let feed = |mut system: System, window: usize, threshold: usize, steps: usize| {
+            let mut detector = DivergenceDetector::new(window, threshold);
+            if detector.observe(system.total_energy()) {
+                return Some(0);
+            }
+            (1..=steps).find(|_| {
+                system.step();
+                detector.observe(system.total_energy())
+            })
+        };
+
+        // A lone drifting body gains 4 energy per step.
+        let drifting = System::new_with_velocities(vec![(
+            Position { x: 1, y: 0, z: 0 },
+            Velocity { x: 1, y: 1, z: 0 },
+        )]);
+        assert_eq!(feed(drifting.clone(), 10, 20, 100), Some(10));
+        assert_eq!(feed(drifting.clone(), 10, 39, 100), Some(10));
+        assert_eq!(feed(drifting, 10, 40, 100), None);
+
+        assert_eq!(feed(System::aoc_example_1(), 10, 0, 500), None);
+        assert_eq!(feed(System::aoc_example_1(), 50, 0, 500), None);
+    }
+
+    #[test]
+    fn groups() {
+        let first = vec![
+            Position { x: -1, y: 0, z: 2 },
+            Position { x: 4, y: -8, z: 8 },
+        ];
+        let second = vec![
+            Position {
+                x: 2,
+                y: -10,
+                z: -7,
+            },
+            Position { x: 3, y: 5, z: -1 },
+        ];
+        let mut a = System::new(first.clone());
+        let mut b = System::new(second.clone());
+
+        let mut grouped = System::new(vec![first[0], second[0], first[1], second[1]]);
+        grouped.set_group(1, 7);
+        grouped.set_group(3, 7);
+        assert_eq!(grouped.body(3).map(|b| b.group()), Some(7));
+        assert_eq!(grouped.to_dot(100.0).matches(" -- ").count(), 2);
+
+        for _ in 0..50 {
+            a.step();
+            b.step();
+            grouped.step();
+            let state = grouped.state();
+            let (sa, sb) = (a.state(), b.state());
+            assert_eq!(&state[0..6], &sa[0..6]);
+            assert_eq!(&state[6..12], &sb[0..6]);
+            assert_eq!(&state[12..18], &sa[6..12]);
+            assert_eq!(&state[18..24], &sb[6..12]);
+        }
+    }
+
+    #[test]
+    fn step_bodies() {
+        let mut system = System::aoc_example_1();
+        let mut bodies: Vec<Body> = system
+            .bodies()
+            .map(|b| Body::with_velocity(b.position(), b.velocity()))
+            .collect();
+        for _ in 0..10 {
+            system.step();
+            super::step_bodies(&mut bodies, 1);
+        }
+        assert_eq!(bodies.iter().cloned().collect::<System>(), system);
+
+        let mut scaled = vec![
+            Body::new(Position { x: 0, y: 0, z: 0 }),
+            Body::new(Position { x: 5, y: -5, z: 0 }),
+        ];
+        super::step_bodies(&mut scaled, 3);
+        assert_eq!(scaled[0].velocity, Velocity { x: 3, y: -3, z: 0 });
+        assert_eq!(scaled[1].position, Position { x: 2, y: -2, z: 0 });
+    }
+
+    #[test]
+    fn find_period() {
+        assert_eq!(System::aoc_example_1().find_period(1000), Some(2772));
+        assert_eq!(
+            System::aoc_example_2().find_period(1_000_000),
+            Some(4_686_774_924)
+        );
+
+        let mut grouped = System::aoc_example_1();
+        grouped.set_group(3, 1);
+        assert_eq!(grouped.find_period(1_000_000), Some(15470));
+
+        // Only the checked policy matches `Axis1D`; the others step the
+        // whole system and find the same period.
+        let mut wrapping = System::aoc_example_1();
+        wrapping.set_overflow_policy(OverflowPolicy::Wrapping);
+        assert!(!wrapping.is_plain());
+        assert_eq!(wrapping.find_period(1000), Some(2772));
+    }
+
+    #[test]
+    fn find_period_with_max_speed() {
+        let mut clamped = System::aoc_example_1();
+        clamped.set_max_speed(Some(1));
+        assert_eq!(clamped.find_period(usize::MAX), None);
+    }
+
+    #[test]
+    fn run_result() {
+        let result = System::aoc_example_1().run(10);
+        assert_eq!(
+            result,
+            RunResult {
+                energy_at_steps: 10,
+                total_energy: 179,
+                period: Some(2772),
+            }
+        );
+
+        let drifting = System::new_with_velocities(vec![
+            (Position::new(), Velocity::new()),
+            (Position { x: 3, y: 0, z: 0 }, Velocity { x: 0, y: 1, z: 0 }),
+        ]);
+        assert_eq!(drifting.run(10).period, None);
+        assert_eq!(drifting.run(10).energy_at_steps, 10);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn run_result_json() {
+        let result = RunResult {
+            energy_at_steps: 1000,
+            total_energy: 8287,
+            period: Some(2772),
+        };
+        assert_eq!(
+            serde_json::to_value(&result).unwrap(),
+            serde_json::json!({"energy_at_steps": 1000, "total_energy": 8287, "period": 2772})
+        );
+
+        let aperiodic = RunResult {
+            period: None,
+            ..result
+        };
+        assert_eq!(
+            serde_json::to_value(&aperiodic).unwrap()["period"],
+            serde_json::Value::Null
+        );
+    }
+
+    #[test]
+    fn step_with_force() {
+        let mut system = System::aoc_example_1();
+        let before = system.momentum();
+        for _ in 0..10 {
+            system.step_with_force(|_, _| Velocity { x: 1, y: 0, z: -2 });
+        }
+        assert_eq!(system.momentum(), before);
+        // The first body is first in all three of its pairs.
+        assert_eq!(
+            system.body(0).unwrap().velocity(),
+            Velocity {
+                x: 30,
+                y: 0,
+                z: -60
+            }
+        );
+
+        let mut builtin = System::aoc_example_1();
+        let mut custom = builtin.clone();
+        builtin.step();
+        custom.step_with_force(|a, b| a.calc_gravity(b, 1.0));
+        assert_eq!(custom, builtin);
+    }
+
+    #[test]
+    fn arrays() {
+        let v = Vec3 { x: 1, y: -2, z: 3 };
+        assert_eq!(v.as_array(), [1, -2, 3]);
+        assert_eq!(Vec3::from_array(v.as_array()), v);
+
+        let system = System::aoc_example_1();
+        let flat = system.positions_flat();
+        assert_eq!(flat.len(), 12);
+        assert_eq!(&flat[..6], &[-1, 0, 2, 2, -10, -7]);
+        for (chunk, body) in flat.chunks(3).zip(system.bodies()) {
+            assert_eq!(
+                Vec3::from_array([chunk[0], chunk[1], chunk[2]]),
+                body.position()
+            );
+        }
+    }
+
+    #[test]
+    fn subcycle_structure() {
+        // Flattening z makes it a fixed point with period 1.
+        let positions = System::aoc_example_1()
+            .bodies()
+            .map(|b| Position {
+                z: 0,
+                ..b.position()
+            })
+            .collect();
+        let info = System::new(positions).subcycle_structure(100).unwrap();
+        assert_eq!(info.periods, [18, 28, 1]);
+        assert_eq!(info.divides, vec![(Axis::Z, Axis::X), (Axis::Z, Axis::Y)]);
+    }
+
+    #[test]
+    fn kick() {
+        let drift = Velocity { x: 1, y: -1, z: 2 };
+        let mut system = System::aoc_example_1();
+        let before = system.momentum();
+        system.kick_all(drift);
+        assert_eq!(system.momentum() - before, Velocity { x: 4, y: -4, z: 8 });
+        assert_eq!(system.relative_velocity(0, 1), Some(Velocity::new()));
+
+        system.kick(2, drift);
+        assert_eq!(
+            system.body(2).unwrap().velocity(),
+            Velocity { x: 2, y: -2, z: 4 }
+        );
+    }
+
+    #[test]
+    fn run_monitored() {
+        let mut system = System::aoc_example_2();
+        assert_eq!(system.run_monitored(100, 0), Ok(()));
+
+        let mut count = 0;
+        let corrupted = |system: &mut System| {
+            system.step();
+            count += 1;
+            if count == 3 {
+                system.kick(0, Velocity { x: 2, y: 0, z: -1 });
+            }
+        };
+        assert_eq!(
+            system.monitor(10, 2, corrupted),
+            Err(MonitorError::Drift { step: 3, drift: 3 })
+        );
+
+        let mut clamped = System::aoc_example_2();
+        clamped.set_max_speed(Some(1));
+        assert_eq!(
+            clamped.run_monitored(10, 0),
+            Err(MonitorError::NotConserved)
+        );
+        assert_eq!(clamped, {
+            let mut unstepped = System::aoc_example_2();
+            unstepped.set_max_speed(Some(1));
+            unstepped
+        });
+
+        let mut pinned = System::aoc_example_2();
+        pinned.fix_body(0);
+        assert_eq!(pinned.run_monitored(10, 0), Err(MonitorError::NotConserved));
+        pinned.unfix_body(0);
+        assert_eq!(pinned.run_monitored(10, 0), Ok(()));
+    }
+
+    #[test]
+    fn inertia_tensor() {
+        let system = System::new(vec![
+            Position { x: 5, y: 1, z: 1 },
+            Position { x: 3, y: 1, z: 1 },
+            Position { x: 4, y: 3, z: 1 },
+            Position { x: 4, y: -1, z: 1 },
+        ]);
+        assert_eq!(
+            system.inertia_tensor(),
+            [[8.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 10.0]]
+        );
+        assert_eq!(System::new(vec![]).inertia_tensor(), [[0.0; 3]; 3]);
+    }
+
+    #[test]
+    fn deltas_sum_to_zero() {
+        let mut system = System::aoc_example_2();
+        system.set_group(3, 1);
+        for _ in 0..20 {
+            let deltas = system.compute_deltas();
+            let net = deltas.iter().fold(Velocity::new(), |mut sum, &d| {
+                sum += d;
+                sum
+            });
+            assert_eq!(net, Velocity::new());
+            assert_eq!(deltas[3], Velocity::new());
+
+            let before: Vec<Velocity> = system.bodies().map(|b| b.velocity()).collect();
+            system.step();
+            for ((body, v), d) in system.bodies().zip(before).zip(deltas) {
+                assert_eq!(body.velocity() - v, d);
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn find_cycle() {
+        use std::hash::BuildHasherDefault;
+
+        #[derive(Default)]
+        struct Fnv(u64);
+
+        impl Hasher for Fnv {
+            fn finish(&self) -> u64 {
+                self.0
+            }
+
+            fn write(&mut self, bytes: &[u8]) {
+                for &byte in bytes {
+                    self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100_0000_01b3);
+                }
+            }
+        }
+
+        let system = System::aoc_example_1();
+        assert_eq!(system.find_cycle(3000), Some((0, 2772)));
+        assert_eq!(
+            system.find_cycle_with_hasher(3000, BuildHasherDefault::<Fnv>::default()),
+            Some((0, 2772))
+        );
+        assert_eq!(system.find_cycle(100), None);
+    }
+
+    #[test]
+    fn step_with_callback() {
+        let mut system = System::aoc_example_1();
+        let mut seen = Vec::new();
+        let taken = system.step_with_callback(10, |step, s| {
+            seen.push((step, s.total_energy()));
+            step < 4
+        });
+        assert_eq!(taken, 4);
+        assert_eq!(
+            seen.iter().map(|&(step, _)| step).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+
+        let mut expected = System::aoc_example_1();
+        for _ in 0..4 {
+            expected.step();
+        }
+        assert_eq!(system, expected);
+        assert_eq!(seen[3].1, expected.total_energy());
+        assert_eq!(system.step_with_callback(6, |_, _| true), 6);
+    }
+
+    #[test]
+    fn state_at() {
+        let system = System::aoc_example_2();
+        let mut naive = system.clone();
+        for _ in 0..1000 {
+            naive.step();
+        }
+        assert_eq!(system.state_at(1000), naive.state());
+        assert_eq!(system.state_at(0), system.state());
+        assert_eq!(system.state_at(4_686_774_924 * 1000), system.state());
+    }
+
+    #[test]
+    fn from_initial() {
+        assert_eq!(
+            System::from_initial(Preset::AocExample1),
+            Ok(System::aoc_example_1())
+        );
+        let parsed = System::from_initial(
+            "<x=-1, y=0, z=2>\n<x=2, y=-10, z=-7>\n<x=4, y=-8, z=8>\n<x=3, y=5, z=-1>",
+        )
+        .unwrap();
+        assert_eq!(parsed, System::aoc_example_1());
+        #[cfg(feature = "std")]
+        {
+            let input: &[u8] = b"<x=-1, y=0, z=2>\n<x=2, y=-10, z=-7>\n\
+                                 <x=4, y=-8, z=8>\n<x=3, y=5, z=-1>\n";
+            let read = System::from_initial(BodyReader(input)).unwrap();
+            assert_eq!(read, System::aoc_example_1());
+            assert!(matches!(
+                System::from_initial(BodyReader(&b"<x=1>\n"[..])),
+                Err(ParseError::Malformed(_))
+            ));
+        }
+
+        let bodies = vec![Body::with_velocity(
+            Position::new(),
+            Velocity { x: 1, y: 0, z: 0 },
+        )];
+        assert_eq!(System::from_initial(bodies).unwrap().len(), 1);
+        assert!(matches!(
+            System::from_initial("<x=1>"),
+            Err(ParseError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn max_speed() {
+        let mut unlimited = System::aoc_example_2();
+        for _ in 0..100 {
+            unlimited.step();
+        }
+        let fastest = |system: &System| {
+            system
+                .bodies()
+                .flat_map(|b| b.velocity().as_array())
+                .map(isize::unsigned_abs)
+                .max()
+                .unwrap()
+        };
+        assert!(fastest(&unlimited) > 3);
+
+        let mut clamped = System::aoc_example_2();
+        clamped.set_max_speed(Some(3));
+        for _ in 0..100 {
+            clamped.step();
+            assert!(fastest(&clamped) <= 3);
+        }
+
+        clamped.set_max_speed(None);
+        let mut reference = clamped.clone();
+        clamped.step();
+        reference.step_with_force(|a, b| a.calc_gravity(b, 1.0));
+        assert_eq!(clamped, reference);
+    }
+
+    #[test]
+    fn dominant_pair() {
+        // Every pair pulls on all three axes, so the closest pair wins.
+        let system = System::new(vec![
+            Position { x: 0, y: 0, z: 0 },
+            Position {
+                x: 10,
+                y: 10,
+                z: 10,
+            },
+            Position { x: 1, y: 1, z: 1 },
+        ]);
+        assert_eq!(system.dominant_pair(), Some((0, 2)));
+
+        let mut weighted = system.clone();
+        weighted
+            .set_interaction_matrix(vec![
+                vec![1.0, 3.0, 1.0],
+                vec![3.0, 1.0, 1.0],
+                vec![1.0, 1.0, 1.0],
+            ])
+            .unwrap();
+        assert_eq!(weighted.dominant_pair(), Some((0, 1)));
+
+        let lone = System::new(vec![Position::new(), Position::new()]);
+        assert_eq!(lone.dominant_pair(), None);
+    }
+
+    #[test]
+    fn freeze() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let mut system = System::aoc_example_1();
+        let frozen = system.freeze();
+        assert_send_sync(&frozen);
+        let shared = Arc::clone(&frozen);
+        for _ in 0..10 {
+            system.step();
+        }
+        assert_eq!(shared.len(), 4);
+        assert_eq!(shared.total_energy(), 0);
+        assert_eq!(
+            shared.body(1).unwrap().position(),
+            Position {
+                x: 2,
+                y: -10,
+                z: -7
+            }
+        );
+        assert_eq!(
+            *system.freeze(),
+            FrozenState {
+                bodies: system.bodies.clone()
+            }
+        );
+        assert_ne!(system.freeze(), frozen);
+    }
+
+    #[test]
+    fn fix_body() {
+        let center = Position::new();
+        let mut system = System::new_with_velocities(vec![
+            (center, Velocity { x: 1, y: 0, z: 0 }),
+            (Position { x: 5, y: 0, z: 0 }, Velocity { x: 0, y: 2, z: 0 }),
+            (
+                Position { x: -5, y: 0, z: 0 },
+                Velocity { x: 0, y: -2, z: 0 },
+            ),
+        ]);
+        system.fix_body(0);
+        assert!(system.body(0).unwrap().is_fixed());
+        let start = system.clone();
+        for _ in 0..50 {
+            let deltas = system.compute_deltas();
+            system.step();
+            assert_eq!(deltas[0], Velocity::new());
+            assert_eq!(system.body(0).unwrap().position(), center);
+            assert_eq!(
+                system.body(0).unwrap().velocity(),
+                Velocity { x: 1, y: 0, z: 0 }
+            );
+        }
+        // The others are pulled back towards the pinned center.
+        let satellite = system.body(1).unwrap();
+        assert_ne!(satellite.position(), start.body(1).unwrap().position());
+        assert!(satellite.position().x.abs() <= 5);
+
+        system.unfix_body(0);
+        system.step();
+        assert_ne!(system.body(0).unwrap().position(), center);
+    }
+
+    #[test]
+    fn step_returns_changed() {
+        let mut system = System::new_with_velocities(vec![
+            (Position { x: 0, y: 3, z: 0 }, Velocity { x: 2, y: 0, z: 0 }),
+            (
+                Position { x: 4, y: 3, z: 0 },
+                Velocity { x: -2, y: 0, z: 0 },
+            ),
+        ]);
+        system.set_max_speed(Some(0));
+        assert!(system.step_returns_changed());
+        let settled = system.clone();
+        assert!(!system.step_returns_changed());
+        assert_eq!(system, settled);
+
+        let mut at_rest = System::new(vec![Position::new(), Position::new()]);
+        assert!(!at_rest.step_returns_changed());
+        assert!(System::aoc_example_1().step_returns_changed());
+    }
+
+    #[test]
+    fn to_table() {
+        let system = System::new_with_velocities(vec![
+            (
+                Position { x: -1, y: 0, z: 2 },
+                Velocity { x: 3, y: -1, z: 0 },
+            ),
+            (
+                Position {
+                    x: 12,
+                    y: -100,
+                    z: 7,
+                },
+                Velocity::new(),
+            ),
+        ]);
+        let table = system.to_table();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "body   x     y  z  vx  vy  vz  energy");
+        assert_eq!(lines[1], "   0  -1     0  2   3  -1   0      12");
+        assert_eq!(lines[2], "   1  12  -100  7   0   0   0       0");
+    }
+
+    #[cfg(feature = "nalgebra")]
+    #[test]
+    fn nalgebra() {
+        use nalgebra::Vector3;
+
+        let v = Vector3::new(1.5, -2.0, 0.25);
+        let ours = Vec3::from(v);
+        assert_eq!(
+            ours,
+            Vec3 {
+                x: 1.5,
+                y: -2.0,
+                z: 0.25
+            }
+        );
+        assert_eq!(Vector3::from(ours), v);
+
+        let position: Position = Vector3::new(-1, 0, 2).into();
+        assert_eq!(position, Position { x: -1, y: 0, z: 2 });
+    }
+
+    #[test]
+    fn checksum() {
+        let mut system = System::aoc_example_1();
+        let mut other = System::aoc_example_1();
+        assert_eq!(system.checksum(), other.checksum());
+        assert_eq!(system.checksum(), 12_683_082_941_031_034_959);
+
+        other.kick(3, Velocity { x: 0, y: 0, z: 1 });
+        assert_ne!(system.checksum(), other.checksum());
+
+        system.step();
+        assert_ne!(system.checksum(), System::aoc_example_1().checksum());
+    }
+
+    #[test]
+    fn two_phase_step() {
+        let mut system = System::aoc_example_2();
+        system.set_group(2, 1);
+        let mut reference = system.clone();
+        for _ in 0..50 {
+            let before = system.clone();
+            let deltas = system.compute_deltas();
+            assert_eq!(system, before);
+            assert_eq!(system.compute_deltas(), deltas);
+
+            system.apply_deltas(&deltas);
+            reference.step();
+            assert_eq!(system, reference);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn step_for() {
+        let mut system = System::aoc_example_1();
+        let budget = Duration::from_millis(20);
+        let start = Instant::now();
+        let steps = system.step_for(budget);
+        let elapsed = start.elapsed();
+        assert!(steps > 0);
+        assert!(elapsed >= budget);
+        assert!(elapsed < budget + Duration::from_secs(1));
+
+        let mut expected = System::aoc_example_1();
+        for _ in 0..steps {
+            expected.step();
+        }
+        assert_eq!(system, expected);
+    }
+}
@@ -1,124 +1,12 @@
-use itertools::Itertools;
-use std::cell::RefCell;
-use std::convert::TryInto;
-use std::ops::AddAssign;
-use std::rc::Rc;
+use gravity_simulator::{Position, System};
+use log::info;
 use std::collections::HashSet;
 
-#[derive(Debug, Copy, Clone)]
-struct Vec3 {
-    x: isize,
-    y: isize,
-    z: isize,
-}
-
-impl Vec3 {
-    fn new() -> Self {
-        Self { x: 0, y: 0, z: 0 }
-    }
-}
-
-type Velocity = Vec3;
-type Position = Vec3;
-
-impl AddAssign<Velocity> for Position {
-    fn add_assign(&mut self, other: Velocity) {
-        self.x += other.x;
-        self.y += other.y;
-        self.z += other.z;
-    }
-}
-
-#[derive(Debug)]
-struct Body {
-    position: Position,
-    velocity: Velocity,
-}
-
-impl Body {
-    fn new(position: Position) -> Self {
-        Self {
-            position,
-            velocity: Velocity::new(),
-        }
-    }
-
-    fn calc_gravity(&mut self, other: Rc<RefCell<Self>>) {
-        let dx = self.position.x - other.borrow().position.x;
-        let dy = self.position.y - other.borrow().position.y;
-        let dz = self.position.z - other.borrow().position.z;
-
-        self.velocity.x -= dx.signum();
-        self.velocity.y -= dy.signum();
-        self.velocity.z -= dz.signum();
-
-        other.borrow_mut().velocity.x += dx.signum();
-        other.borrow_mut().velocity.y += dy.signum();
-        other.borrow_mut().velocity.z += dz.signum();
-    }
-
-    fn update_pos(&mut self) {
-        self.position += self.velocity;
-    }
-
-    fn potential_energy(&self) -> usize {
-        (self.position.x.abs() + self.position.y.abs() + self.position.z.abs())
-            .try_into()
-            .unwrap()
-    }
-
-    fn kinetic_energy(&self) -> usize {
-        (self.velocity.x.abs() + self.velocity.y.abs() + self.velocity.z.abs())
-            .try_into()
-            .unwrap()
-    }
-
-    fn total_energy(&self) -> usize {
-        self.potential_energy() * self.kinetic_energy()
-    }
-}
-
-#[derive(Debug)]
-struct System {
-    bodies: Vec<Rc<RefCell<Body>>>,
-}
-
-impl System {
-    fn new(positions: Vec<Position>) -> Self {
-        let bodies = positions
-            .into_iter()
-            .map(|p| Rc::new(RefCell::new(Body::new(p))))
-            .collect();
-        Self { bodies }
-    }
-
-    fn step(&mut self) {
-        for pair in self.bodies.iter().combinations(2) {
-            pair[0].borrow_mut().calc_gravity(pair[1].clone());
-        }
-
-        self.bodies.iter().for_each(|b| b.borrow_mut().update_pos())
-    }
-
-    fn total_energy(&self) -> usize {
-        self.bodies.iter().map(|b| b.borrow().total_energy()).sum()
-    }
-
-    fn state(&self) -> Vec<isize> {
-        let mut vec = Vec::new();
-        for body in self.bodies.iter() {
-            vec.push(body.borrow().position.x);
-            vec.push(body.borrow().position.y);
-            vec.push(body.borrow().position.z);
-            vec.push(body.borrow().velocity.x);
-            vec.push(body.borrow().velocity.y);
-            vec.push(body.borrow().velocity.z);
-        }
-        vec
-    }
-}
+const STEPS: usize = 1000;
 
 fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
     let positions = vec![
         Position {
             x: -19,
@@ -139,21 +27,69 @@ fn main() {
     ];
     let mut system = System::new(positions);
 
+    if std::env::args().any(|arg| arg == "--json") {
+        let result = system.run(STEPS);
+        println!(
+            "{}",
+            serde_json::to_string(&result).expect("RunResult always serializes")
+        );
+        return;
+    }
+
+    let progress = std::env::args().any(|arg| arg == "--progress");
+    let report_interval = match report_interval(std::env::args()) {
+        Ok(interval) => interval,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
     let mut states = HashSet::new();
-    let mut count = 0;
-    loop {
-        system.step();
-        count += 1;
-        if count == 1000 {
-            println!("Total energy: {}", system.total_energy());
-            break; // break here because obviously carrying on is going to fail
+    let mut duplicate = false;
+    // stop at 1000 because obviously carrying on is going to fail
+    let count = system.step_with_callback(STEPS, |step, s| {
+        if progress {
+            if let Some(percent) = progress_milestone(step, STEPS) {
+                info!("{}% complete", percent);
+            }
         }
-        if !states.insert(system.state()) {
-            println!("Found a duplicate state after {} iterations", count);
-            break;
+        if report_interval.is_some_and(|n| is_report_step(step, n)) {
+            info!("step {}: total energy {}", step, s.total_energy());
         }
+        duplicate = !states.insert(s.state_hash());
+        !duplicate
+    });
+    if duplicate {
+        info!("Found a duplicate state after {} iterations", count);
+    } else {
+        info!("Total energy: {}", system.total_energy());
     }
+}
+
+/// The percentage to report after `step` of `total`, on every 10% boundary.
+fn progress_milestone(step: usize, total: usize) -> Option<usize> {
+    let tenths = step * 10 / total;
+    if tenths > (step - 1) * 10 / total {
+        Some(tenths * 10)
+    } else {
+        None
+    }
+}
+
+/// The value of `--report-interval N`, if given. `N` must be a positive
+/// integer.
+fn report_interval<I: Iterator<Item = String>>(mut args: I) -> Result<Option<usize>, String> {
+    if !args.any(|arg| arg == "--report-interval") {
+        return Ok(None);
+    }
+    match args.next().map(|n| n.parse()) {
+        Some(Ok(n)) if n > 0 => Ok(Some(n)),
+        _ => Err("--report-interval needs a positive number of steps".to_string()),
+    }
+}
 
+fn is_report_step(step: usize, interval: usize) -> bool {
+    step.is_multiple_of(interval)
 }
 
 #[cfg(test)]
@@ -161,42 +97,38 @@ mod tests {
     use super::*;
 
     #[test]
-    fn example1() {
-        let positions = vec![
-            Position { x: -1, y: 0, z: 2 },
-            Position {
-                x: 2,
-                y: -10,
-                z: -7,
-            },
-            Position { x: 4, y: -8, z: 8 },
-            Position { x: 3, y: 5, z: -1 },
-        ];
-        let mut system = System::new(positions);
+    fn progress_milestones() {
+        let milestones: Vec<(usize, usize)> = (1..=1000)
+            .filter_map(|step| progress_milestone(step, 1000).map(|p| (step, p)))
+            .collect();
+        let expected: Vec<(usize, usize)> = (1..=10).map(|i| (i * 100, i * 10)).collect();
+        assert_eq!(milestones, expected);
 
-        for _ in 0..10 {
-            system.step()
-        }
-        assert_eq!(system.total_energy(), 179);
+        let short: Vec<usize> = (1..=4)
+            .filter_map(|step| progress_milestone(step, 4))
+            .collect();
+        assert_eq!(short, vec![20, 50, 70, 100]);
     }
 
     #[test]
-    fn example2() {
-        let positions = vec![
-            Position {
-                x: -8,
-                y: -10,
-                z: 0,
-            },
-            Position { x: 5, y: 5, z: 10 },
-            Position { x: 2, y: -7, z: 3 },
-            Position { x: 9, y: -8, z: -3 },
-        ];
-        let mut system = System::new(positions);
-
-        for _ in 0..100 {
-            system.step()
-        }
-        assert_eq!(system.total_energy(), 1940);
+    fn report_steps() {
+        let args = |line: &str| {
+            line.split(' ')
+                .map(String::from)
+                .collect::<Vec<_>>()
+                .into_iter()
+        };
+        assert_eq!(
+            report_interval(args("sim --report-interval 250")),
+            Ok(Some(250))
+        );
+        assert_eq!(report_interval(args("sim --progress")), Ok(None));
+        assert!(report_interval(args("sim --report-interval")).is_err());
+        assert!(report_interval(args("sim --report-interval 0")).is_err());
+
+        let steps: Vec<usize> = (1..=1000)
+            .filter(|&step| is_report_step(step, 250))
+            .collect();
+        assert_eq!(steps, vec![250, 500, 750, 1000]);
     }
 }
@@ -0,0 +1,310 @@
+//! Parsing of the Advent of Code `<x=1, y=2, z=3>` body format.
+
+use crate::{Body, Position, System, Vec3};
+use alloc::string::{String, ToString};
+use core::convert::TryFrom;
+use core::error::Error;
+use core::fmt;
+use core::num::ParseIntError;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::io::{self, BufRead};
+
+#[derive(Debug)]
+pub enum ParseError {
+    /// The input isn't shaped like `<x=.., y=.., z=..>`.
+    Malformed(String),
+    InvalidNumber(ParseIntError),
+    #[cfg(feature = "std")]
+    Io(io::Error),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Malformed(input) => write!(f, "expected `<x=.., y=.., z=..>`, got `{}`", input),
+            Self::InvalidNumber(e) => write!(f, "invalid coordinate: {}", e),
+            #[cfg(feature = "std")]
+            Self::Io(e) => write!(f, "failed to read input: {}", e),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::InvalidNumber(e) => Some(e),
+            #[cfg(feature = "std")]
+            Self::Io(e) => Some(e),
+            Self::Malformed(_) => None,
+        }
+    }
+}
+
+impl From<ParseIntError> for ParseError {
+    fn from(e: ParseIntError) -> Self {
+        Self::InvalidNumber(e)
+    }
+}
+
+impl TryFrom<&str> for Vec3 {
+    type Error = ParseError;
+
+    fn try_from(input: &str) -> Result<Self, ParseError> {
+        let malformed = || ParseError::Malformed(input.to_string());
+        let inner = input
+            .trim()
+            .strip_prefix('<')
+            .and_then(|s| s.strip_suffix('>'))
+            .ok_or_else(malformed)?;
+
+        let mut parts = inner.split(',');
+        let mut component = |name: &str| -> Result<isize, ParseError> {
+            let mut pair = parts.next().ok_or_else(malformed)?.splitn(2, '=');
+            if pair.next().map(str::trim) != Some(name) {
+                return Err(malformed());
+            }
+            Ok(pair.next().ok_or_else(malformed)?.trim().parse()?)
+        };
+        let vec = Vec3 {
+            x: component("x")?,
+            y: component("y")?,
+            z: component("z")?,
+        };
+        if parts.next().is_some() {
+            return Err(malformed());
+        }
+        Ok(vec)
+    }
+}
+
+/// Parses one body position per line; the bodies start at rest. Blank lines
+/// and lines starting with `#` are ignored.
+impl FromStr for System {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Self, ParseError> {
+        input.lines().filter_map(parse_line).collect()
+    }
+}
+
+/// Lazily parses bodies one line at a time, in the same format as
+/// `System::from_str`, without reading the whole input up front.
+#[cfg(feature = "std")]
+pub fn parse_bodies<R: BufRead>(reader: R) -> impl Iterator<Item = Result<Body, ParseError>> {
+    reader.lines().filter_map(|line| match line {
+        Ok(line) => parse_line(&line),
+        Err(e) => Some(Err(ParseError::Io(e))),
+    })
+}
+
+/// Starting bodies read from `R` in the `System::from_str` format, for
+/// `System::from_initial`.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct BodyReader<R>(pub R);
+
+#[cfg(feature = "std")]
+impl<R: BufRead> crate::InitialConditions for BodyReader<R> {
+    type Error = ParseError;
+
+    fn bodies(self) -> Result<Vec<Body>, ParseError> {
+        parse_bodies(self.0).collect()
+    }
+}
+
+/// Reads several systems from one input. Systems are separated by a blank
+/// line or a `---` line, and each block uses the `System::from_str` format.
+/// Runs of separators don't produce empty systems.
+#[cfg(feature = "std")]
+pub fn parse_systems<R: BufRead>(reader: R) -> Result<Vec<System>, ParseError> {
+    let mut systems = Vec::new();
+    let mut bodies = Vec::new();
+    for line in reader.lines() {
+        let line = line.map_err(ParseError::Io)?;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed == "---" {
+            if !bodies.is_empty() {
+                systems.push(bodies.drain(..).collect());
+            }
+        } else if let Some(body) = parse_line(&line) {
+            bodies.push(body?);
+        }
+    }
+    if !bodies.is_empty() {
+        systems.push(bodies.into_iter().collect());
+    }
+    Ok(systems)
+}
+
+/// `None` for blank and comment lines.
+fn parse_line(line: &str) -> Option<Result<Body, ParseError>> {
+    let trimmed = line.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return None;
+    }
+    Some(Position::try_from(line).map(Body::new))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn vec3_valid() {
+        assert_eq!(
+            Vec3::try_from("<x=-1, y=0, z=2>").unwrap(),
+            Vec3 { x: -1, y: 0, z: 2 }
+        );
+    }
+
+    #[test]
+    fn vec3_whitespace() {
+        assert_eq!(
+            Vec3::try_from("  < x = 14 ,y=-10,   z= 3 >\t").unwrap(),
+            Vec3 {
+                x: 14,
+                y: -10,
+                z: 3
+            }
+        );
+    }
+
+    #[test]
+    fn vec3_malformed() {
+        for input in [
+            "x=1, y=2, z=3",
+            "<x=1, y=2>",
+            "<x=1, y=2, z=3, w=4>",
+            "<y=1, x=2, z=3>",
+            "<x=1, y=2, z>",
+            "",
+        ] {
+            assert!(matches!(
+                Vec3::try_from(input),
+                Err(ParseError::Malformed(ref s)) if s == input
+            ));
+        }
+        assert!(matches!(
+            Vec3::try_from("<x=1, y=two, z=3>"),
+            Err(ParseError::InvalidNumber(_))
+        ));
+    }
+
+    #[test]
+    fn system() {
+        let system: System =
+            "<x=-1, y=0, z=2>\n<x=2, y=-10, z=-7>\n<x=4, y=-8, z=8>\n<x=3, y=5, z=-1>"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            system,
+            System::new(vec![
+                Position { x: -1, y: 0, z: 2 },
+                Position {
+                    x: 2,
+                    y: -10,
+                    z: -7
+                },
+                Position { x: 4, y: -8, z: 8 },
+                Position { x: 3, y: 5, z: -1 },
+            ])
+        );
+    }
+
+    #[test]
+    fn system_with_comments() {
+        let input = "# example 1 from the puzzle\n\
+                     <x=-1, y=0, z=2>\n\
+                     \n\
+                     # the next one is far away\n\
+                     <x=2, y=-10, z=-7>\n\
+                     \t\n\
+                     <x=4, y=-8, z=8>\n\
+                     <x=3, y=5, z=-1>\n";
+        let system: System = input.parse().unwrap();
+        assert_eq!(
+            system,
+            System::new(vec![
+                Position { x: -1, y: 0, z: 2 },
+                Position {
+                    x: 2,
+                    y: -10,
+                    z: -7
+                },
+                Position { x: 4, y: -8, z: 8 },
+                Position { x: 3, y: 5, z: -1 },
+            ])
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn streaming() {
+        use std::io::BufReader;
+
+        let input: &[u8] = b"# two bodies\n<x=-1, y=0, z=2>\n\n<x=2, y=-10, z=-7>\n";
+        let mut bodies = parse_bodies(BufReader::new(input));
+        assert_eq!(
+            bodies.next().unwrap().unwrap(),
+            Body::new(Position { x: -1, y: 0, z: 2 })
+        );
+        assert_eq!(
+            bodies.next().unwrap().unwrap(),
+            Body::new(Position {
+                x: 2,
+                y: -10,
+                z: -7
+            })
+        );
+        assert!(bodies.next().is_none());
+
+        let system: System = parse_bodies(BufReader::new(input))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            system,
+            System::new(vec![
+                Position { x: -1, y: 0, z: 2 },
+                Position {
+                    x: 2,
+                    y: -10,
+                    z: -7
+                },
+            ])
+        );
+
+        let bad: &[u8] = b"<x=1, y=2, z=3>\n<x=1, y=2>\n";
+        let results: Vec<_> = parse_bodies(BufReader::new(bad)).collect();
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(ParseError::Malformed(_))));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn batch() {
+        // A whitespace-only line separates systems like a blank one, and
+        // a `---` followed by a blank line is still a single separator.
+        let input: &[u8] = b"# first\n\
+                             <x=-1, y=0, z=2>\n\
+                             <x=2, y=-10, z=-7>\n\
+                             \t \n\
+                             <x=4, y=-8, z=8>\n\
+                             ---\n\
+                             \n\
+                             <x=3, y=5, z=-1>\n\
+                             <x=0, y=0, z=0>\n\
+                             <x=1, y=1, z=1>\n";
+        let systems = parse_systems(input).unwrap();
+        let sizes: Vec<usize> = systems.iter().map(System::len).collect();
+        assert_eq!(sizes, vec![2, 1, 3]);
+        assert_eq!(
+            systems[1],
+            System::new(vec![Position { x: 4, y: -8, z: 8 }])
+        );
+
+        let bad: &[u8] = b"<x=1, y=2, z=3>\n---\n<x=1, y=2>\n";
+        assert!(matches!(parse_systems(bad), Err(ParseError::Malformed(_))));
+    }
+}
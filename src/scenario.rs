@@ -0,0 +1,183 @@
+//! Scenarios: a system and how long to run it, loaded from TOML.
+//!
+//! ```toml
+//! steps = 1000
+//!
+//! [[bodies]]
+//! position = { x = -1, y = 0, z = 2 }
+//!
+//! [[bodies]]
+//! position = { x = 2, y = -10, z = -7 }
+//! velocity = { x = 0, y = 1, z = 0 }
+//! ```
+//!
+//! Unknown keys are rejected, so a file written for a model with masses, a
+//! time step or a gravitational constant fails to load instead of running
+//! with those settings silently ignored.
+
+use crate::{Body, Position, RunResult, System, Velocity};
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub bodies: Vec<ScenarioBody>,
+    /// Steps to simulate before measuring energy.
+    pub steps: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioBody {
+    pub position: Position,
+    /// Bodies start at rest unless given a velocity.
+    #[serde(default)]
+    pub velocity: Velocity,
+}
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to read scenario: {}", e),
+            Self::Toml(e) => write!(f, "invalid scenario: {}", e),
+        }
+    }
+}
+
+impl Error for ScenarioError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Toml(e) => Some(e),
+        }
+    }
+}
+
+impl Scenario {
+    /// Reads and parses a scenario file.
+    pub fn from_toml<P: AsRef<Path>>(path: P) -> Result<Self, ScenarioError> {
+        fs::read_to_string(path).map_err(ScenarioError::Io)?.parse()
+    }
+
+    pub fn system(&self) -> System {
+        self.bodies
+            .iter()
+            .map(|b| Body::with_velocity(b.position, b.velocity))
+            .collect()
+    }
+
+    pub fn run(&self) -> RunResult {
+        self.system().run(self.steps)
+    }
+}
+
+impl FromStr for Scenario {
+    type Err = ScenarioError;
+
+    fn from_str(input: &str) -> Result<Self, ScenarioError> {
+        toml::from_str(input).map_err(ScenarioError::Toml)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE_1: &str = "
+        steps = 10
+
+        [[bodies]]
+        position = { x = -1, y = 0, z = 2 }
+
+        [[bodies]]
+        position = { x = 2, y = -10, z = -7 }
+        velocity = { x = 0, y = 0, z = 0 }
+
+        [[bodies]]
+        position = { x = 4, y = -8, z = 8 }
+
+        [[bodies]]
+        position = { x = 3, y = 5, z = -1 }
+    ";
+
+    #[test]
+    fn run() {
+        let scenario: Scenario = EXAMPLE_1.parse().unwrap();
+        assert_eq!(scenario.system(), System::aoc_example_1());
+        assert_eq!(
+            scenario.run(),
+            RunResult {
+                energy_at_steps: 10,
+                total_energy: 179,
+                period: Some(2772),
+            }
+        );
+    }
+
+    #[test]
+    fn run_with_velocity() {
+        // The example from the module docs: the moving body drifts the
+        // system off, so there's no period, but the run still finishes.
+        let input = "
+            steps = 1000
+
+            [[bodies]]
+            position = { x = -1, y = 0, z = 2 }
+
+            [[bodies]]
+            position = { x = 2, y = -10, z = -7 }
+            velocity = { x = 0, y = 1, z = 0 }
+        ";
+        let scenario: Scenario = input.parse().unwrap();
+        let mut system = scenario.system();
+        for _ in 0..1000 {
+            system.step();
+        }
+        assert_eq!(
+            scenario.run(),
+            RunResult {
+                energy_at_steps: 1000,
+                total_energy: system.total_energy(),
+                period: None,
+            }
+        );
+    }
+
+    #[test]
+    fn from_file() {
+        let path = std::env::temp_dir().join(format!(
+            "gravity-simulator-scenario-test-{}.toml",
+            std::process::id()
+        ));
+        fs::write(&path, EXAMPLE_1).unwrap();
+        let scenario = Scenario::from_toml(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(scenario.unwrap().steps, 10);
+
+        assert!(matches!(
+            Scenario::from_toml(std::env::temp_dir().join("no-such-scenario.toml")),
+            Err(ScenarioError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_unsupported_settings() {
+        let input = "steps = 1\ndt = 0.01\n[[bodies]]\nposition = { x = 0, y = 0, z = 0 }\n";
+        assert!(matches!(
+            input.parse::<Scenario>(),
+            Err(ScenarioError::Toml(_))
+        ));
+    }
+}
@@ -0,0 +1,99 @@
+//! Dimension-generic counterparts of `Vec3` and `Body`. `Body` computes its
+//! energies through `BodyN<3>`; `Axis1D` is the single-axis simulation.
+
+use crate::{Body, Vec3};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct VecN<const D: usize>(pub [isize; D]);
+
+impl<const D: usize> VecN<D> {
+    /// Sum of the absolute values of all `D` components, saturating at
+    /// `usize::MAX`.
+    pub fn manhattan(&self) -> usize {
+        self.0
+            .iter()
+            .fold(0, |sum: usize, c| sum.saturating_add(c.unsigned_abs()))
+    }
+}
+
+impl<const D: usize> Default for VecN<D> {
+    fn default() -> Self {
+        Self([0; D])
+    }
+}
+
+impl From<Vec3> for VecN<3> {
+    fn from(v: Vec3) -> Self {
+        Self([v.x, v.y, v.z])
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BodyN<const D: usize> {
+    pub position: VecN<D>,
+    pub velocity: VecN<D>,
+}
+
+impl<const D: usize> BodyN<D> {
+    pub fn new(position: VecN<D>, velocity: VecN<D>) -> Self {
+        Self { position, velocity }
+    }
+
+    pub fn potential_energy(&self) -> usize {
+        self.position.manhattan()
+    }
+
+    pub fn kinetic_energy(&self) -> usize {
+        self.velocity.manhattan()
+    }
+
+    pub fn total_energy(&self) -> usize {
+        self.potential_energy()
+            .saturating_mul(self.kinetic_energy())
+    }
+}
+
+impl From<&Body> for BodyN<3> {
+    fn from(body: &Body) -> Self {
+        Self::new(body.position.into(), body.velocity.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Position, Velocity};
+
+    #[test]
+    fn one_dimension() {
+        let body = BodyN::new(VecN([-7]), VecN([3]));
+        assert_eq!(body.potential_energy(), 7);
+        assert_eq!(body.kinetic_energy(), 3);
+        assert_eq!(body.total_energy(), 21);
+    }
+
+    #[test]
+    fn two_dimensions() {
+        let body = BodyN::new(VecN([4, -2]), VecN([0, -5]));
+        assert_eq!(body.potential_energy(), 6);
+        assert_eq!(body.kinetic_energy(), 5);
+        assert_eq!(body.total_energy(), 30);
+    }
+
+    #[test]
+    fn three_dimensions() {
+        let body = Body::with_velocity(
+            Position { x: 2, y: 1, z: -3 },
+            Velocity { x: -3, y: -2, z: 1 },
+        );
+        let generic = BodyN::from(&body);
+        assert_eq!(generic, BodyN::new(VecN([2, 1, -3]), VecN([-3, -2, 1])));
+        assert_eq!(generic.potential_energy(), 6);
+        assert_eq!(generic.kinetic_energy(), 6);
+        assert_eq!(generic.total_energy(), body.total_energy());
+        assert_eq!(
+            BodyN::<3>::new(VecN::default(), VecN([1, 1, 1])).total_energy(),
+            0
+        );
+    }
+}
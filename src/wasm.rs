@@ -0,0 +1,65 @@
+//! JavaScript bindings, enabled by the `wasm` feature.
+//!
+//! Bodies cross the boundary as JSON arrays of `{"x": .., "y": .., "z": ..}`
+//! objects. The wrapper only forwards to `System`; no simulation logic lives
+//! here.
+
+use crate::{Position, System};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct WasmSystem {
+    system: System,
+}
+
+#[wasm_bindgen]
+impl WasmSystem {
+    /// Builds a system at rest from a JSON array of positions.
+    #[wasm_bindgen(constructor)]
+    pub fn new(json: &str) -> Result<WasmSystem, JsValue> {
+        let positions: Vec<Position> =
+            serde_json::from_str(json).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(Self {
+            system: System::new(positions),
+        })
+    }
+
+    pub fn step(&mut self) {
+        self.system.step()
+    }
+
+    /// Current positions in the same JSON format accepted by the constructor.
+    pub fn state_json(&self) -> String {
+        let positions: Vec<Position> = self.system.bodies().map(|b| b.position()).collect();
+        serde_json::to_string(&positions).expect("positions always serialize")
+    }
+
+    /// Current positions flattened as `[x0, y0, z0, x1, ...]`; a `Float64Array`
+    /// in JS.
+    pub fn positions(&self) -> Vec<f64> {
+        self.system
+            .bodies()
+            .flat_map(|b| b.position().as_array())
+            .map(|c| c as f64)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_round_trip() {
+        let json = r#"[{"x":-1,"y":0,"z":2},{"x":2,"y":-10,"z":-7}]"#;
+        let mut system = WasmSystem::new(json).unwrap();
+        assert_eq!(system.state_json(), json);
+
+        system.step();
+        assert_eq!(
+            system.state_json(),
+            r#"[{"x":0,"y":-1,"z":1},{"x":1,"y":-9,"z":-6}]"#
+        );
+        assert_eq!(system.positions(), vec![0.0, -1.0, 1.0, 1.0, -9.0, -6.0]);
+    }
+}